@@ -0,0 +1,50 @@
+-- Thin client for `neorg-task-sync nvim`, which speaks msgpack-RPC on stdio.
+local M = {}
+
+local channel = nil
+
+local function get_channel()
+  if channel == nil then
+    channel = vim.fn.jobstart({ "neorg-task-sync", "nvim" }, {
+      rpc = true,
+      on_exit = function()
+        channel = nil
+      end,
+    })
+  end
+  return channel
+end
+
+local function buffer_request(bufnr)
+  bufnr = bufnr or vim.api.nvim_get_current_buf()
+  return {
+    filename = vim.api.nvim_buf_get_name(bufnr),
+    lines = vim.api.nvim_buf_get_lines(bufnr, 0, -1, false),
+    modified = vim.bo[bufnr].modified,
+  }
+end
+
+local function apply(bufnr, update)
+  if update.changed then
+    vim.api.nvim_buf_set_lines(bufnr, 0, -1, false, update.lines)
+  end
+  return update.todos
+end
+
+function M.list_todos(bufnr)
+  return vim.rpcrequest(get_channel(), "list_todos", buffer_request(bufnr))
+end
+
+function M.sync_buffer(bufnr)
+  bufnr = bufnr or vim.api.nvim_get_current_buf()
+  return apply(bufnr, vim.rpcrequest(get_channel(), "sync_buffer", buffer_request(bufnr)))
+end
+
+function M.complete_task(bufnr, line)
+  bufnr = bufnr or vim.api.nvim_get_current_buf()
+  local request = buffer_request(bufnr)
+  request.line = line or (vim.api.nvim_win_get_cursor(0)[1] - 1)
+  return apply(bufnr, vim.rpcrequest(get_channel(), "complete_task", request))
+end
+
+return M
@@ -0,0 +1,190 @@
+use chrono::{Local, NaiveDate, Weekday};
+use console::style;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::auth;
+use crate::cfg::CFG;
+use crate::error::Error;
+use crate::opts::Agenda as AgendaOpts;
+use crate::parse::{ParsedNorg, State};
+use crate::sync::workspace_files;
+use crate::tasks::{get_tasks, Task};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Group {
+    Overdue,
+    Today,
+    ThisWeek,
+    Later,
+    NoDate,
+}
+
+impl Group {
+    fn of(date: Option<NaiveDate>, today: NaiveDate) -> Self {
+        let end_of_week = today.week(Weekday::Mon).last_day();
+        match date {
+            Some(date) if date < today => Group::Overdue,
+            Some(date) if date == today => Group::Today,
+            Some(date) if date <= end_of_week => Group::ThisWeek,
+            Some(_) => Group::Later,
+            None => Group::NoDate,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Group::Overdue => "Overdue",
+            Group::Today => "Today",
+            Group::ThisWeek => "This week",
+            Group::Later => "Later",
+            Group::NoDate => "No date",
+        }
+    }
+}
+
+// A local due date wins over the remote one, the day of a journal file is the fallback.
+fn todo_date(
+    due_at: Option<NaiveDate>,
+    remote_due: Option<NaiveDate>,
+    file_day: Option<NaiveDate>,
+) -> Option<NaiveDate> {
+    due_at.or(remote_due).or(file_day)
+}
+
+struct Entry {
+    date: Option<NaiveDate>,
+    content: Arc<str>,
+    // None for tasks that only exist remotely
+    location: Option<(PathBuf, usize)>,
+}
+
+pub async fn perform_agenda(opts: &AgendaOpts) -> Result<(), Error> {
+    let files = workspace_files(&opts.files_or_folders[..])?;
+    let tasks = if opts.offline {
+        Vec::new()
+    } else {
+        get_tasks(auth::login().await?, &CFG.tasklist).await?
+    };
+    let remote: HashMap<Arc<str>, &Task> = tasks.iter().map(|t| (t.id.clone(), t)).collect();
+    let mut present = HashSet::new();
+
+    let mut entries = Vec::new();
+    for file in &files {
+        let norg = ParsedNorg::open(file)?;
+        present.extend(norg.todos.iter().filter_map(|t| t.id.clone()));
+
+        // todos of a journal file are meant to be done on its day
+        let file_day = norg.parse_filename_day().ok();
+        for todo in norg.todos.iter().filter(|t| t.state != State::Done) {
+            let remote_due = todo
+                .id
+                .as_ref()
+                .and_then(|id| remote.get(id))
+                .and_then(|t| t.due_at);
+            entries.push(Entry {
+                date: todo_date(todo.due_at, remote_due, file_day),
+                content: todo.content.clone(),
+                location: Some((file.clone(), todo.line)),
+            });
+        }
+    }
+
+    entries.extend(
+        tasks
+            .iter()
+            .filter(|t| !t.completed && !present.contains(&t.id))
+            .map(|t| Entry {
+                date: t.due_at,
+                content: t.title.clone(),
+                location: None,
+            }),
+    );
+
+    let today = Local::now().date_naive();
+    entries.sort_by(|a, b| {
+        (Group::of(a.date, today), a.date, &a.location).cmp(&(
+            Group::of(b.date, today),
+            b.date,
+            &b.location,
+        ))
+    });
+
+    let mut current_group = None;
+    for entry in &entries {
+        let group = Group::of(entry.date, today);
+        if current_group != Some(group) {
+            if current_group.is_some() {
+                println!();
+            }
+            let title = style(group.title()).bold();
+            match group {
+                Group::Overdue => println!("{}", title.red()),
+                Group::Today => println!("{}", title.green()),
+                _ => println!("{title}"),
+            }
+            current_group = Some(group);
+        }
+        print_entry(entry);
+    }
+    Ok(())
+}
+
+fn print_entry(entry: &Entry) {
+    let date = entry
+        .date
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+    let location = match entry.location {
+        // norg line numbers are 1-based
+        Some((ref file, line)) => format!("{}:{}", file.display(), line + 1),
+        None => "(remote only)".into(),
+    };
+    println!(
+        "  {date:<10} {content} {location}",
+        content = entry.content,
+        location = style(location).dim(),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn day(d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(2023, 10, d)
+    }
+
+    #[test]
+    fn group_of() {
+        // a wednesday
+        let today = day(18).unwrap();
+        for (date, group) in [
+            (day(1), Group::Overdue),
+            (day(17), Group::Overdue),
+            (day(18), Group::Today),
+            (day(19), Group::ThisWeek),
+            (day(22), Group::ThisWeek),
+            (day(23), Group::Later),
+            (None, Group::NoDate),
+        ] {
+            assert_eq!(Group::of(date, today), group, "{date:?}");
+        }
+    }
+
+    #[test]
+    fn date_precedence() {
+        for (due_at, remote_due, file_day, want) in [
+            (day(1), day(2), day(3), day(1)),
+            (None, day(2), day(3), day(2)),
+            (None, None, day(3), day(3)),
+            (day(1), None, day(3), day(1)),
+            (None, None, None, None),
+        ] {
+            assert_eq!(todo_date(due_at, remote_due, file_day), want);
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use chrono::{DateTime, Duration, Local};
+use console::style;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::auth;
+use crate::cfg::CFG;
+use crate::error::Error;
+use crate::opts::Archive as ArchiveOpts;
+use crate::parse::{to_top_level, ParsedNorg, State, Todo};
+use crate::state::SyncState;
+use crate::sync::get_files_from_folders;
+use crate::tasks::{get_tasks, Task};
+
+#[derive(Debug, Clone)]
+pub struct ArchiveStats {
+    pub file: PathBuf,
+    pub archive: PathBuf,
+    pub num_archived: usize,
+}
+
+impl std::fmt::Display for ArchiveStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{file}: {arrow} {num} → {archive}",
+            file = self.file.display(),
+            arrow = style("⤓").bold().green(),
+            num = style(self.num_archived).green(),
+            archive = self.archive.display(),
+        )
+    }
+}
+
+pub async fn perform_archive(opts: &ArchiveOpts) -> Result<(), Error> {
+    let days = opts
+        .older_than_days
+        .or(CFG.archive_completed_todos_older_than_days)
+        .ok_or_else(|| Error::NotFound {
+            what: "age of todos to archive (--older-than-days or config value: archive_completed_todos_older_than_days)".into(),
+        })?;
+
+    let files = get_files_from_folders(&opts.files_or_folders[..], &CFG.ignore_filenames[..])?;
+    let tasks = if opts.offline {
+        Vec::new()
+    } else {
+        get_tasks(auth::login().await?, &CFG.tasklist).await?
+    };
+
+    let mut state = SyncState::load()?;
+    let archived = archive(
+        &files[..],
+        &tasks[..],
+        &mut state,
+        Duration::days(days as i64),
+    )?;
+    state.store()?;
+
+    for stats in archived {
+        println!("{stats}");
+    }
+    Ok(())
+}
+
+// Move completed todos that were completed longer ago than the given duration into the archive
+// section (or sibling archive file). Completion time is taken from the remote task if available,
+// otherwise from when the todo was first seen completed locally. Items with open nested todos
+// stay, and first-seen times of todos that are no longer completed (or gone) are forgotten.
+pub fn archive(
+    files: &[PathBuf],
+    tasks: &[Task],
+    state: &mut SyncState,
+    older_than: Duration,
+) -> Result<Vec<ArchiveStats>, Error> {
+    let now = Local::now();
+    let completed_at: HashMap<Arc<str>, DateTime<Local>> = tasks
+        .iter()
+        .filter_map(|t| t.completed_at.map(|c| (t.id.clone(), c)))
+        .collect();
+
+    // state keys of the todos in the processed files, completed and any state
+    let mut seen_done = HashSet::new();
+    let mut present = HashSet::new();
+    let mut file_prefixes = Vec::new();
+
+    let mut stats = Vec::new();
+    for file in files {
+        if is_archive_file(file) {
+            log::debug!("not archiving the archive itself: {}", file.display());
+            continue;
+        }
+        let mut norg = ParsedNorg::open(file)?;
+        file_prefixes.push(format!("{}:", file.display()));
+        present.extend(norg.todos.iter().map(|t| state_key(file, t)));
+
+        // todos in the archive section are already archived
+        let archived_lines = match CFG.archive_file {
+            None => norg
+                .sections
+                .iter()
+                .find(|s| s.title == CFG.archive_section)
+                .map(|s| s.line..norg.section_end(s.line)),
+            Some(_) => None,
+        };
+
+        let mut to_archive = Vec::new();
+        for todo in norg.todos.iter().filter(|t| {
+            t.state == State::Done
+                && !archived_lines
+                    .as_ref()
+                    .is_some_and(|lines| lines.contains(&t.line))
+        }) {
+            let key = state_key(file, todo);
+            seen_done.insert(key.clone());
+            let first_seen = *state.done_first_seen.entry(key).or_insert(now);
+            let done_at = todo
+                .id
+                .as_ref()
+                .and_then(|id| completed_at.get(id))
+                .cloned()
+                .unwrap_or(first_seen);
+
+            if now - done_at > older_than {
+                to_archive.push(todo.line);
+            }
+        }
+
+        let spans = archivable_spans(&norg, &to_archive[..]);
+        if spans.is_empty() {
+            continue;
+        }
+
+        // completed todos nested in archived items are gone as well
+        for todo in norg
+            .todos
+            .iter()
+            .filter(|t| t.state == State::Done && spans.iter().any(|s| s.contains(&t.line)))
+        {
+            state.done_first_seen.remove(&state_key(file, todo));
+        }
+        let num_archived = spans.len();
+        let archived = take_items(&mut norg, &spans[..])?;
+
+        let archive = match CFG.archive_file {
+            None => {
+                insert_into_archive_section(&mut norg, archived)?;
+                norg.filename.clone()
+            }
+            Some(ref name) => {
+                let path = file.with_file_name(name.as_ref());
+                let mut norg_archive = if path.exists() {
+                    let norg_archive = ParsedNorg::open(&path)?;
+                    norg_archive.backup()?;
+                    norg_archive
+                } else {
+                    ParsedNorg::from_source(&path, Vec::new(), now)?
+                };
+                insert_into_archive_section(&mut norg_archive, archived)?;
+                norg_archive.write()?;
+                path
+            }
+        };
+
+        norg.backup()?;
+        norg.write()?;
+
+        stats.push(ArchiveStats {
+            file: file.clone(),
+            archive,
+            num_archived,
+        });
+    }
+
+    // Forget todos that were completed but are now open again or gone, so they do not start out
+    // as completed long ago once completed again. Ids of todos in other files are only known to
+    // be gone if they are not among the remote tasks.
+    state.done_first_seen.retain(|key, _| {
+        let file_key = key.contains(':');
+        let gone = if file_key {
+            file_prefixes
+                .iter()
+                .any(|prefix| key.starts_with(prefix.as_str()))
+        } else {
+            !tasks.is_empty() && !tasks.iter().any(|t| t.id == *key)
+        };
+        seen_done.contains(key) || !(present.contains(key) || gone)
+    });
+
+    Ok(stats)
+}
+
+// Spans of the items to archive, leaving out items with open nested todos, since those would be
+// archived along with them.
+fn archivable_spans(norg: &ParsedNorg, to_archive: &[usize]) -> Vec<Range<usize>> {
+    let done: Vec<usize> = to_archive
+        .iter()
+        .copied()
+        .filter(|line| {
+            norg.item_spans(&[*line]).iter().all(|span| {
+                norg.todos
+                    .iter()
+                    .all(|t| !span.contains(&t.line) || t.state == State::Done)
+            })
+        })
+        .collect();
+    norg.item_spans(&done[..])
+}
+
+fn is_archive_file(file: &Path) -> bool {
+    CFG.archive_file
+        .as_deref()
+        .is_some_and(|name| file.file_name().is_some_and(|f| f == name))
+}
+
+// Remove the given line ranges (sorted and not overlapping), returns the removed lines. Nested
+// items are lowered to the top list level, as they lose their parent.
+fn take_items(norg: &mut ParsedNorg, spans: &[Range<usize>]) -> Result<Vec<Vec<u8>>, Error> {
+    let mut lines = norg.lines();
+    let mut taken = Vec::new();
+    for span in spans.iter().rev() {
+        let mut removed = to_top_level(lines.drain(span.clone()).collect());
+        removed.append(&mut taken);
+        taken = removed;
+    }
+    norg.set_lines(&lines[..])?;
+    Ok(taken)
+}
+
+fn state_key(file: &Path, todo: &Todo) -> Arc<str> {
+    match todo.id {
+        Some(ref id) => id.clone(),
+        None => format!("{}:{}", file.display(), todo.content).into(),
+    }
+}
+
+// Insert lines right below the archive heading, which is appended if not present.
+fn insert_into_archive_section(
+    norg: &mut ParsedNorg,
+    new_lines: Vec<Vec<u8>>,
+) -> Result<(), Error> {
+    let mut lines = norg.lines();
+
+    let line_section = match norg
+        .sections
+        .iter()
+        .find(|s| s.title == CFG.archive_section)
+    {
+        Some(section) => section.line,
+        None => {
+            // keep trailing newline at the end of the file
+            let mut line = match lines.last() {
+                Some(last) if last.is_empty() => lines.len() - 1,
+                _ => lines.len(),
+            };
+            if line > 0 && !lines[line - 1].is_empty() {
+                lines.insert(line, Vec::new());
+                line += 1;
+            }
+            lines.insert(line, format!("* {}", CFG.archive_section).into_bytes());
+            line
+        }
+    };
+
+    for (i, new_line) in new_lines.into_iter().enumerate() {
+        lines.insert(line_section + 1 + i, new_line);
+    }
+    norg.set_lines(&lines[..])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    static GIVEN: &str = r###"* TODOs
+  - (x) Parent %#taskid foobar1%
+    continued on the next line
+  -- ( ) Child
+  - ( ) Still open
+  -- (x) Done child
+  - (x) Done as well
+  -- (x) Done as well child
+"###;
+
+    #[test]
+    fn archive_whole_items() -> Result<(), Error> {
+        let mut norg = ParsedNorg::from_source(
+            Path::new("2023-10-18.norg"),
+            GIVEN.as_bytes().to_vec(),
+            Local::now(),
+        )?;
+        let spans = archivable_spans(&norg, &[1, 5, 6, 7]);
+        assert_eq!(spans, vec![5..6, 6..8]);
+        let archived = take_items(&mut norg, &spans[..])?;
+        insert_into_archive_section(&mut norg, archived)?;
+
+        let want = format!(
+            "* TODOs\n  - (x) Parent %#taskid foobar1%\n    continued on the next line\n  -- ( ) Child\n  - ( ) Still open\n\n* {}\n  - (x) Done child\n  - (x) Done as well\n  -- (x) Done as well child\n",
+            CFG.archive_section
+        );
+        assert_eq!(String::from_utf8_lossy(norg.source_code()), want);
+        Ok(())
+    }
+
+    #[test]
+    fn prune_stale_state() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("2023-10-18.norg");
+        std::fs::write(
+            &file,
+            "* TODOs\n  - ( ) Reopened %#taskid reopened%\n  - (x) Done\n",
+        )?;
+        let long_ago = Local::now() - Duration::days(10);
+        let key_edited: Arc<str> = format!("{}:Edited", file.display()).into();
+        let key_done: Arc<str> = format!("{}:Done", file.display()).into();
+        let mut state = SyncState::default();
+        for key in [
+            "reopened".into(),
+            "elsewhere".into(),
+            "other.norg:Elsewhere".into(),
+            key_edited,
+        ] {
+            state.done_first_seen.insert(key, long_ago);
+        }
+
+        let stats = archive(&[file], &[], &mut state, Duration::days(1))?;
+        assert!(stats.is_empty());
+
+        let mut keys: Vec<&str> = state.done_first_seen.keys().map(|k| k.as_ref()).collect();
+        keys.sort_unstable();
+        let mut want = vec!["elsewhere", "other.norg:Elsewhere", key_done.as_ref()];
+        want.sort_unstable();
+        assert_eq!(keys, want);
+        Ok(())
+    }
+
+    #[test]
+    fn archive_into_existing_section() -> Result<(), Error> {
+        let given = format!(
+            "* TODOs\n  - (x) New\n\n* {}\n  - (x) Old\n",
+            CFG.archive_section
+        );
+        let mut norg = ParsedNorg::from_source(
+            Path::new("2023-10-18.norg"),
+            given.into_bytes(),
+            Local::now(),
+        )?;
+        let spans = norg.item_spans(&[1]);
+        let archived = take_items(&mut norg, &spans[..])?;
+        insert_into_archive_section(&mut norg, archived)?;
+
+        let want = format!(
+            "* TODOs\n\n* {}\n  - (x) New\n  - (x) Old\n",
+            CFG.archive_section
+        );
+        assert_eq!(String::from_utf8_lossy(norg.source_code()), want);
+        Ok(())
+    }
+}
@@ -1,52 +1,332 @@
+use chrono::{Local, TimeZone};
+use console::style;
 use google_tasks1::oauth2::authenticator::Authenticator as OAuthenticator;
+use google_tasks1::oauth2::authenticator_delegate::InstalledFlowDelegate;
 use google_tasks1::oauth2::hyper::client::Client;
-use google_tasks1::oauth2::read_application_secret;
 use google_tasks1::oauth2::InstalledFlowAuthenticator;
 use google_tasks1::oauth2::InstalledFlowReturnMethod;
 use hyper::client::HttpConnector;
+use hyper::{Body, Method, Request, StatusCode};
 use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::future::Future;
+use std::pin::Pin;
+use tokio::io::AsyncBufReadExt;
 
-use crate::cfg::clientsecret_name;
 use crate::cfg::tokencache_name;
+use crate::cfg::CFG;
+use crate::credentials::{
+    erase_tokens, load_tokens, read_client_secret, token_location, token_store,
+};
 use crate::error::Error;
 use crate::error::WrapError;
 
-const SCOPES: [&str; 2] = [
+pub const SCOPES: [&str; 2] = [
     "https://www.googleapis.com/auth/tasks",
     "https://www.googleapis.com/auth/tasks.readonly",
 ];
 
+/// Only requested when logging in explicitly, to show the account in `auth status`. Tokens
+/// without it still cover all other scopes.
+pub const ACCOUNT_SCOPE: &str = "https://www.googleapis.com/auth/userinfo.email";
+
 pub type Authenticator = OAuthenticator<HttpsConnector<HttpConnector>>;
 
+/// How the authorization code gets from the browser to us, and what access is requested.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoginFlow {
+    /// Read the redirect URL from stdin instead of waiting for the browser to reach the local
+    /// redirect server (e.g. when logging in via SSH on a machine without browser).
+    pub headless: bool,
+
+    /// Port of the local redirect server (default: config value `oauth_redirect_port`, random if
+    /// not configured).
+    pub port: Option<u16>,
+
+    /// Also request the email address of the account (`ACCOUNT_SCOPE`).
+    pub account: bool,
+}
+
 pub async fn login() -> Result<Authenticator, Error> {
-    // Read application secret from a file.
-    log::debug!("reading client secret: {}", clientsecret_name());
-    let secret = read_application_secret(clientsecret_name())
-        .await
-        .during("reading clientsecret")?;
+    login_with(LoginFlow::default()).await
+}
+
+pub async fn login_with(flow: LoginFlow) -> Result<Authenticator, Error> {
+    let secret = read_client_secret().await?;
 
     // Create the token cache folder if it doesn't exist.
     if let Some(token_folder) = std::path::PathBuf::from(tokencache_name()).parent() {
-        if !token_folder.exists() {
+        if CFG.token_store_command.is_none() && !token_folder.exists() {
             std::fs::create_dir_all(token_folder).during("creating folder for token")?;
         }
     }
 
+    let return_method = match flow.port.or(CFG.oauth_redirect_port) {
+        Some(port) => InstalledFlowReturnMethod::HTTPPortRedirect(port),
+        None => InstalledFlowReturnMethod::HTTPRedirect,
+    };
+
+    let builder = InstalledFlowAuthenticator::builder(secret, return_method)
+        .flow_delegate(Box::new(LoginDelegate {
+            headless: flow.headless,
+        }))
+        .hyper_client(https_client());
+    let builder = match token_store()? {
+        Some(store) => builder.with_storage(Box::new(store)),
+        None => builder.persist_tokens_to_disk(tokencache_name()),
+    };
+    let auth = builder.build().await.during("creating authenticator")?;
+
+    let _ = if flow.account {
+        auth.token(&[SCOPES[0], SCOPES[1], ACCOUNT_SCOPE]).await
+    } else {
+        auth.token(&SCOPES).await
+    }
+    .during("obtaining auth token")?;
+
+    Ok(auth)
+}
+
+/// Show the cached token without triggering a login.
+pub async fn status() -> Result<(), Error> {
+    let Some(tokens) = load_tokens().await? else {
+        println!("not logged in (no tokens in {})", token_location());
+        return Ok(());
+    };
+
+    println!("tokens: {}", token_location());
+    for cached in tokens {
+        let token = cached.token;
+        let expires_at = token
+            .expires_at
+            .and_then(|t| Local.timestamp_opt(t.unix_timestamp(), 0).single());
+        let expired = expires_at.is_some_and(|t| t <= Local::now());
+
+        // Only query the token info endpoint with a still valid access token; refreshing it
+        // would count as using the token.
+        let info = match token.access_token {
+            Some(ref access_token) if !expired => Some(token_info(access_token).await),
+            _ => None,
+        };
+        let has_account_scope = cached.scopes.iter().any(|s| s == ACCOUNT_SCOPE);
+
+        println!();
+        match info {
+            Some(Ok(TokenInfo {
+                email: Some(ref email),
+                ..
+            })) => println!("account: {email}"),
+            _ if !has_account_scope => println!(
+                "account: unknown, run `neorg-task-sync auth login` to grant access to the email address"
+            ),
+            _ => println!("account: unknown while the access token is not valid"),
+        }
+        println!("scopes: {}", cached.scopes.join(" "));
+        match expires_at {
+            Some(t) if expired => println!(
+                "access token: {} at {} (refreshed on next use)",
+                style("expired").yellow(),
+                t.format("%Y-%m-%d %H:%M")
+            ),
+            Some(t) => println!(
+                "access token: {} until {}",
+                style("valid").green(),
+                t.format("%Y-%m-%d %H:%M")
+            ),
+            None => println!("access token: no expiry"),
+        }
+        match token.refresh_token {
+            Some(_) => println!("refresh token: {}", style("present").green()),
+            None => println!("refresh token: {}", style("missing").red()),
+        }
+        match info {
+            Some(Ok(TokenInfo {
+                accepted: false, ..
+            })) => println!(
+                "{}: access token was rejected, it might have been revoked",
+                style("warning").yellow()
+            ),
+            Some(Err(err)) => println!(
+                "{}: unknown whether the access token is accepted: {err}",
+                style("warning").yellow()
+            ),
+            Some(Ok(_)) | None => {}
+        }
+    }
+    Ok(())
+}
+
+/// Revoke the cached token (unless `local_only`) and delete the token cache.
+pub async fn logout(local_only: bool) -> Result<(), Error> {
+    let Some(tokens) = load_tokens().await? else {
+        println!("not logged in (no tokens in {})", token_location());
+        return Ok(());
+    };
+
+    if !local_only {
+        for cached in tokens {
+            // Revoking the refresh token also revokes its access tokens.
+            if let Some(token) = cached.token.refresh_token.or(cached.token.access_token) {
+                revoke(&token).await?;
+            }
+        }
+    }
+
+    erase_tokens().await?;
+    println!("logged out, deleted tokens in {}", token_location());
+    Ok(())
+}
+
+/// What Google reports about an access token.
+pub struct TokenInfo {
+    /// Whether the token is (still) valid.
+    pub accepted: bool,
+    /// Email address of the account, only if the token covers `ACCOUNT_SCOPE`.
+    pub email: Option<String>,
+}
+
+// Ask Google about the access token, which is sent as header to keep it out of URLs.
+pub async fn token_info(access_token: &str) -> Result<TokenInfo, Error> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("https://oauth2.googleapis.com/tokeninfo")
+        .header("Authorization", format!("Bearer {access_token}"))
+        .body(Body::empty())
+        .expect("valid request");
+    let response = https_client()
+        .request(request)
+        .await
+        .during("querying token info")?;
+    if !response.status().is_success() {
+        return Ok(TokenInfo {
+            accepted: false,
+            email: None,
+        });
+    }
+
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .during("reading token info")?;
+    let info: serde_json::Value = serde_json::from_slice(&body)?;
+    Ok(TokenInfo {
+        accepted: true,
+        email: info["email"].as_str().map(String::from),
+    })
+}
+
+async fn revoke(token: &str) -> Result<(), Error> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("https://oauth2.googleapis.com/revoke")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(format!("token={token}")))
+        .expect("valid request");
+    let response = https_client()
+        .request(request)
+        .await
+        .during("revoking token")?;
+
+    match response.status() {
+        StatusCode::OK => Ok(()),
+        // token expired or already revoked
+        StatusCode::BAD_REQUEST => {
+            log::warn!("token was already invalid");
+            Ok(())
+        }
+        status => Err(Error::Revoke {
+            message: format!(
+                "unexpected response: {status} (use --local to only delete the cache)"
+            )
+            .into(),
+        }),
+    }
+}
+
+/// Authenticator without secret or token, for tests that must not reach google.
+#[cfg(test)]
+pub async fn offline_authenticator() -> Authenticator {
+    InstalledFlowAuthenticator::builder(Default::default(), InstalledFlowReturnMethod::HTTPRedirect)
+        .hyper_client(https_client())
+        .build()
+        .await
+        .unwrap()
+}
+
+fn https_client() -> Client<HttpsConnector<HttpConnector>> {
     let connector = HttpsConnectorBuilder::new()
         .with_native_roots()
         .https_or_http()
         .enable_http1()
         .enable_http2()
         .build();
+    Client::builder().build(connector)
+}
 
-    let auth = InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::HTTPRedirect)
-        .persist_tokens_to_disk(tokencache_name())
-        .hyper_client(Client::builder().build(connector))
-        .build()
+struct LoginDelegate {
+    headless: bool,
+}
+
+impl InstalledFlowDelegate for LoginDelegate {
+    fn present_user_url<'a>(
+        &'a self,
+        url: &'a str,
+        _need_code: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>> {
+        Box::pin(present_user_url(url, self.headless))
+    }
+}
+
+// The local redirect server is already running when the URL is presented, so in headless mode we
+// hand the pasted redirect URL over to it ourselves.
+async fn present_user_url(url: &str, headless: bool) -> Result<String, String> {
+    static REDIRECT_PORT: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"redirect_uri=https?://[^:/&]+:(\d+)").unwrap());
+    let port = REDIRECT_PORT
+        .captures(url)
+        .and_then(|c| c.get(1))
+        .map(|p| p.as_str().to_owned());
+
+    if !headless {
+        eprintln!(
+            "Please direct your browser to {url} and follow the instructions displayed there."
+        );
+        if let Some(port) = port {
+            eprintln!(
+                "When using a browser on another machine, forward the redirect port first: {}",
+                style(format!("ssh -L {port}:127.0.0.1:{port} <this host>")).bold()
+            );
+        }
+        return Ok(String::new());
+    }
+
+    eprintln!("Open the following URL in a browser on any machine and follow the instructions:\n\n{url}\n");
+    eprintln!(
+        "Afterwards, the browser is redirected to a page on 127.0.0.1 that fails to load. \
+        Paste its full URL (or only the value of its {} parameter) here:",
+        style("code").bold()
+    );
+
+    let mut input = String::new();
+    tokio::io::BufReader::new(tokio::io::stdin())
+        .read_line(&mut input)
         .await
-        .during("creating authenticator")?;
+        .map_err(|e| format!("couldn't read redirect URL: {e}"))?;
+    let input = input.trim();
 
-    let _ = auth.token(&SCOPES).await.during("obtaining auth token")?;
+    let redirect = if input.contains("://") {
+        input.to_owned()
+    } else {
+        let port = port.ok_or("could not determine port of redirect server")?;
+        format!("http://127.0.0.1:{port}/?code={input}")
+    };
+    let redirect: hyper::Uri = redirect
+        .parse()
+        .map_err(|e| format!("invalid redirect URL: {e}"))?;
 
-    Ok(auth)
+    hyper::Client::new()
+        .get(redirect)
+        .await
+        .map_err(|e| format!("could not pass code to redirect server: {e}"))?;
+    Ok(String::new())
 }
@@ -0,0 +1,56 @@
+use console::style;
+use std::slice;
+
+use crate::auth;
+use crate::cfg::CFG;
+use crate::error::{Error, WrapError};
+use crate::journal::{create_today, journal_folder};
+use crate::opts::Capture as CaptureOpts;
+use crate::parse::ParsedNorg;
+use crate::tasks::task_create;
+
+pub async fn perform_capture(opts: &CaptureOpts) -> Result<(), Error> {
+    let folder = match opts.journal {
+        Some(ref journal) => journal_folder(slice::from_ref(journal))?,
+        None => journal_folder(&CFG.workspaces[..])?,
+    };
+    let file = create_today(&folder)?;
+
+    let mut norg = ParsedNorg::open(&file)?;
+    if let Some(ref section) = opts.section {
+        norg.set_todo_section(section)?;
+    }
+
+    // a todo has to fit into a single line
+    let text = opts.text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let line = norg.insert_todo_lines([format!("- ( ) {text}").into_bytes()])?[0];
+
+    // Write right away, so that the todo is kept (and pushed on next sync) if pushing fails.
+    norg.backup()?;
+    norg.write()?;
+    println!("captured {} in {}", style(&text).bold(), file.display());
+
+    if !opts.push {
+        return Ok(());
+    }
+
+    let auth = auth::login().await?;
+    let mut lines = norg.lines();
+    let todo = norg
+        .todos
+        .iter_mut()
+        .find(|t| t.line == line)
+        .ok_or_else(|| Error::NotFound {
+            what: format!("captured todo in line {}", line + 1),
+        })?;
+    todo.due_at = opts.due.or(todo.due_at);
+    let task = task_create(auth, &CFG.tasklist, todo)
+        .await
+        .during("pushing captured todo")?;
+    todo.append_id(&mut lines[line]);
+
+    norg.set_lines(&lines[..])?;
+    norg.write()?;
+    println!("pushed as task {}", task.id);
+    Ok(())
+}
@@ -1,75 +1,342 @@
 use camino::{Utf8Path, Utf8PathBuf};
+use clap::ValueEnum;
 use directories::BaseDirs;
 use figment::{
     providers::{Env, Format, Json, Serialized, Yaml},
-    Figment,
+    value::{Dict, Map},
+    Figment, Metadata, Profile, Provider,
 };
 use google_tasks1::oauth2::parse_application_secret;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use serde::Deserialize;
 use serde::Serialize;
 use std::{
-    fs::{rename, File},
+    fs::rename,
     io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
 use crate::{
     error::{handle_load_error, Error, WrapError},
-    opts::{ImportConfig, ImportTarget, STDIN},
+    opts::{ConfigFormat, ImportConfig, ImportTarget, ShowConfig, STDIN},
 };
 
 pub static CFG: Lazy<Config> = Lazy::new(|| Config::load().during("reading config").unwrap());
 static BASE_DIRS: Lazy<BaseDirs> = Lazy::new(|| BaseDirs::new().expect("failed to get base dirs"));
+static PROFILE: OnceCell<Option<Arc<str>>> = OnceCell::new();
+static LOCAL_CONFIG: OnceCell<Option<PathBuf>> = OnceCell::new();
+
+/// Name of directory-local config files, which override the global config for their tree.
+pub const LOCAL_CONFIG_NAME: &str = ".neorg-task-sync.yaml";
+
+/// Values a directory-local config may set. Local configs come with the (possibly shared) notes,
+/// so they must not control commands that are executed or paths that are read or written.
+pub const LOCAL_CONFIG_KEYS: &[&str] = &[
+    "ignore_filenames",
+    "section_todos",
+    "section_todos_till_end_of_day",
+    "tasklist",
+];
+
+/// Name of the profile that is used if none is selected.
+pub const DEFAULT_PROFILE: &str = "default";
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
+    pub archive_completed_todos_older_than_days: Option<usize>,
+    pub archive_file: Option<Arc<str>>,
+    pub archive_section: Arc<str>,
     pub clear_completed_tasks_older_than_days: Option<usize>,
+    pub client_secret_command: Option<Arc<str>>,
+    pub dashboard_file: Option<Utf8PathBuf>,
     pub ignore_filenames: Vec<Arc<str>>,
+    pub journal_layout: JournalLayout,
+    pub journal_template: Option<Utf8PathBuf>,
+    pub oauth_redirect_port: Option<u16>,
+    pub rollover_leave: RolloverLeave,
     pub tasklist: Arc<str>,
+    pub token_cache_key_file: Option<Utf8PathBuf>,
+    pub token_store_command: Option<Arc<str>>,
     pub section_todos: Arc<str>,
     pub section_todos_till_end_of_day: Option<Arc<str>>,
+    pub workspaces: Vec<Utf8PathBuf>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            archive_completed_todos_older_than_days: None,
+            archive_file: None,
+            archive_section: "Archive".into(),
             clear_completed_tasks_older_than_days: None,
+            client_secret_command: None,
+            dashboard_file: None,
             ignore_filenames: vec!["index.norg".into()],
+            journal_layout: JournalLayout::default(),
+            journal_template: None,
+            oauth_redirect_port: None,
+            rollover_leave: RolloverLeave::default(),
             tasklist: Arc::from(""),
+            token_cache_key_file: None,
+            token_store_command: None,
             section_todos: "TODOs".into(),
             section_todos_till_end_of_day: None,
+            workspaces: Vec::new(),
         }
     }
 }
 
-impl Config {
-    pub fn store_fallback(&self) -> Result<(), Error> {
-        let tmp = config_fallback_name().with_extension(".json.new");
-        serde_json::to_writer(File::create(&tmp)?, self)?;
-        rename(tmp, config_fallback_name())?;
-        Ok(())
-    }
+/// How journal files are organized below the journal folder.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalLayout {
+    /// `<journal>/2023-10-18.norg`
+    #[default]
+    Flat,
+
+    /// `<journal>/2023/10/18.norg`
+    Nested,
+}
+
+/// What to leave behind in the source file when rolling over todos.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RolloverLeave {
+    /// Replace the todo with a link to its new location.
+    #[default]
+    Link,
+
+    /// Keep the todo as cancelled `(_)` item without task id.
+    Marker,
 }
 
 const ERR_INVALID_UTF8: &str = "default path contains non-UTF8";
 
 const DIR: &str = "neorg-task-sync";
 
-fn config_dir() -> Utf8PathBuf {
+const DIR_PROFILES: &str = "profiles";
+
+/// Select the profile to use, has to be called before the config is accessed. The profile has to
+/// exist, unless it is about to be created.
+pub fn set_profile(profile: Option<&str>, create: bool) -> Result<(), Error> {
+    let profile = match profile {
+        None | Some(DEFAULT_PROFILE) => None,
+        Some(name) => Some(check_profile(&base_config_dir(), name, create)?),
+    };
+    if PROFILE.set(profile).is_err() {
+        log::warn!("profile already selected");
+    }
+    Ok(())
+}
+
+/// Name of the selected profile.
+pub fn profile() -> &'static str {
+    PROFILE
+        .get()
+        .and_then(|p| p.as_deref())
+        .unwrap_or(DEFAULT_PROFILE)
+}
+
+/// Look for a directory-local config next to the given paths or in any folder above them
+/// (starting at the current folder if no path is given). All paths have to share the same local
+/// config (or none). Has to be called before the config is accessed.
+pub fn discover_local_config<P: AsRef<Path>>(paths: &[P]) -> Result<(), Error> {
+    let cwd = std::env::current_dir().during("getting current folder")?;
+    let found = local_config_for(&cwd, paths)?;
+    if let Some(ref found) = found {
+        check_local_config(found)?;
+        log::info!("using local config: {}", found.display());
+    }
+    if LOCAL_CONFIG.set(found).is_err() {
+        log::warn!("local config already discovered");
+    }
+    Ok(())
+}
+
+// Local config governing all given paths, relative paths are resolved against `cwd`.
+fn local_config_for<P: AsRef<Path>>(cwd: &Path, paths: &[P]) -> Result<Option<PathBuf>, Error> {
+    let starts: Vec<PathBuf> = if paths.is_empty() {
+        vec![cwd.to_path_buf()]
+    } else {
+        paths.iter().map(|p| cwd.join(p)).collect()
+    };
+
+    let mut governing: Option<(PathBuf, Option<PathBuf>)> = None;
+    for start in starts {
+        let found = find_local_config(&start);
+        match governing {
+            None => governing = Some((start, found)),
+            Some((ref first, ref first_found)) if *first_found != found => {
+                let describe = |path: &Path, found: &Option<PathBuf>| match found {
+                    Some(found) => format!("{} ({})", path.display(), found.display()),
+                    None => format!("{} (none)", path.display()),
+                };
+                return Err(Error::LocalConfigConflict {
+                    first: describe(first, first_found),
+                    second: describe(&start, &found),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(governing.and_then(|(_, found)| found))
+}
+
+fn find_local_config(path: &Path) -> Option<PathBuf> {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let folder = if path.is_file() {
+        path.parent()?.to_path_buf()
+    } else {
+        path
+    };
+    folder
+        .ancestors()
+        .map(|folder| folder.join(LOCAL_CONFIG_NAME))
+        .find(|candidate| candidate.is_file())
+}
+
+// Reject local configs setting values outside of `LOCAL_CONFIG_KEYS`.
+fn check_local_config(path: &Path) -> Result<(), Error> {
+    let invalid = |message: String| Error::InvalidLocalConfig {
+        path: path.display().to_string(),
+        message,
+    };
+
+    let content = std::fs::read_to_string(path).during("reading local config")?;
+    let values: serde_yaml::Value = serde_yaml::from_str(&content)?;
+    let keys: Vec<String> = match values {
+        serde_yaml::Value::Null => Vec::new(),
+        serde_yaml::Value::Mapping(map) => map
+            .keys()
+            .map(|k| match k.as_str() {
+                Some(key) => key.to_owned(),
+                None => format!("{k:?}"),
+            })
+            .collect(),
+        _ => return Err(invalid("expected config values".into())),
+    };
+    match keys
+        .iter()
+        .find(|k| !LOCAL_CONFIG_KEYS.contains(&k.as_str()))
+    {
+        Some(key) => Err(invalid(format!(
+            "{key} cannot be set in a local config, allowed: {}",
+            LOCAL_CONFIG_KEYS.join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Directory-local config in use, if any.
+pub fn local_config_name() -> Option<&'static Path> {
+    LOCAL_CONFIG.get().and_then(|p| p.as_deref())
+}
+
+// A typo must not silently run against a fresh, empty profile.
+fn check_profile(base: &Utf8Path, name: &str, create: bool) -> Result<Arc<str>, Error> {
+    let name = validate_profile_name(name)?;
+    if !create && !profile_dir(base.to_owned(), &name).is_dir() {
+        return Err(Error::ProfileNotFound {
+            name: name.to_string(),
+        });
+    }
+    Ok(name)
+}
+
+fn validate_profile_name(name: &str) -> Result<Arc<str>, Error> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        return Err(Error::InvalidProfile { name: name.into() });
+    }
+    Ok(name.into())
+}
+
+fn base_config_dir() -> Utf8PathBuf {
     Utf8Path::from_path(BASE_DIRS.config_dir())
         .expect(ERR_INVALID_UTF8)
         .to_owned()
         .join(DIR)
 }
 
-fn cache_dir() -> Utf8PathBuf {
+fn base_cache_dir() -> Utf8PathBuf {
     Utf8Path::from_path(BASE_DIRS.cache_dir())
         .expect(ERR_INVALID_UTF8)
         .to_owned()
         .join(DIR)
 }
 
+// The default profile lives directly in the base folders, others in a subfolder each.
+fn profile_dir(base: Utf8PathBuf, profile: &str) -> Utf8PathBuf {
+    match profile {
+        DEFAULT_PROFILE => base,
+        profile => base.join(DIR_PROFILES).join(profile),
+    }
+}
+
+fn config_dir() -> Utf8PathBuf {
+    profile_dir(base_config_dir(), profile())
+}
+
+fn cache_dir() -> Utf8PathBuf {
+    profile_dir(base_cache_dir(), profile())
+}
+
+/// All existing profiles, including the default profile.
+pub fn list_profiles() -> Result<Vec<String>, Error> {
+    let mut profiles = vec![DEFAULT_PROFILE.to_owned()];
+    let folder = base_config_dir().join(DIR_PROFILES);
+    if folder.is_dir() {
+        for entry in folder.read_dir_utf8().during("listing profiles")? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                profiles.push(entry.file_name().to_owned());
+            }
+        }
+    }
+    profiles[1..].sort();
+    Ok(profiles)
+}
+
+/// Create the folders of a new profile.
+pub fn create_profile(name: &str) -> Result<Utf8PathBuf, Error> {
+    let name = validate_profile_name(name)?;
+    let folder = profile_dir(base_config_dir(), &name);
+    if folder.exists() {
+        return Err(Error::ProfileExists {
+            name: name.to_string(),
+        });
+    }
+    std::fs::create_dir_all(&folder).during("creating profile folder")?;
+    Ok(folder)
+}
+
+/// Delete config, secrets, tokens and sync state of a profile.
+pub fn delete_profile(name: &str) -> Result<(), Error> {
+    let name = validate_profile_name(name)?;
+    if &*name == DEFAULT_PROFILE {
+        return Err(Error::InvalidProfile {
+            name: name.to_string(),
+        });
+    }
+    let folder = profile_dir(base_config_dir(), &name);
+    if !folder.exists() {
+        return Err(Error::NotFound {
+            what: format!("profile {name}"),
+        });
+    }
+    std::fs::remove_dir_all(&folder).during("deleting profile config")?;
+    let cache = profile_dir(base_cache_dir(), &name);
+    if cache.exists() {
+        std::fs::remove_dir_all(&cache).during("deleting profile cache")?;
+    }
+    Ok(())
+}
+
 fn config_name() -> Utf8PathBuf {
     config_dir().join("config.yaml")
 }
@@ -86,15 +353,88 @@ pub fn tokencache_name() -> Utf8PathBuf {
     cache_dir().join("tokencache.json")
 }
 
+pub fn tokencache_encrypted_name() -> Utf8PathBuf {
+    cache_dir().join("tokencache.enc")
+}
+
+pub fn state_name() -> Utf8PathBuf {
+    cache_dir().join("state.json")
+}
+
 impl Config {
     pub fn load() -> Result<Self, Error> {
+        Ok(Self::figment().extract()?)
+    }
+
+    /// Config as stored in the global `config.yaml` and the legacy `config-fallback.json`, without
+    /// local config or environment, e.g. to write it back and so migrate the legacy values.
+    pub fn load_global() -> Result<Self, Error> {
         Ok(Figment::new()
             .merge(Yaml::file(config_name()))
-            .merge(Env::prefixed("NEORG_TASK_SYNC_"))
             .join(Json::file(config_fallback_name()))
             .join(Serialized::defaults(Config::default()))
             .extract()?)
     }
+
+    /// Configured workspaces, which a local config cannot set, so they can be read to discover
+    /// the local config.
+    pub fn workspaces() -> Result<Vec<PathBuf>, Error> {
+        let workspaces: Vec<Utf8PathBuf> =
+            Self::figment_with(config_name(), None, config_fallback_name())
+                .extract_inner("workspaces")?;
+        Ok(workspaces.into_iter().map(PathBuf::from).collect())
+    }
+
+    fn figment() -> Figment {
+        Self::figment_with(config_name(), local_config_name(), config_fallback_name())
+    }
+
+    fn figment_with(config: Utf8PathBuf, local: Option<&Path>, fallback: Utf8PathBuf) -> Figment {
+        let mut figment = Figment::new().merge(Tagged(SOURCE_GLOBAL, Yaml::file(config)));
+        // only holds `LOCAL_CONFIG_KEYS`, see `check_local_config`
+        if let Some(local) = local {
+            figment = figment.merge(Tagged(SOURCE_LOCAL, Yaml::file(local)));
+        }
+        figment
+            // the token passphrase is no config value and must not end up in any output
+            .merge(Tagged(
+                SOURCE_ENV,
+                Env::prefixed(ENV_PREFIX).ignore(&["PROFILE", "TOKEN_PASSPHRASE"]),
+            ))
+            .join(Tagged(SOURCE_FALLBACK, Json::file(fallback)))
+            .join(Tagged(
+                SOURCE_DEFAULT,
+                Serialized::defaults(Config::default()),
+            ))
+    }
+}
+
+const ENV_PREFIX: &str = "NEORG_TASK_SYNC_";
+
+// Names of the config providers, to tell where a value comes from in `describe_source`.
+const SOURCE_GLOBAL: &str = "global config";
+const SOURCE_LOCAL: &str = "local config";
+const SOURCE_ENV: &str = "environment";
+const SOURCE_FALLBACK: &str = "legacy fallback config";
+const SOURCE_DEFAULT: &str = "default";
+
+// Provider whose metadata is named by us instead of by figment.
+struct Tagged<P>(&'static str, P);
+
+impl<P: Provider> Provider for Tagged<P> {
+    fn metadata(&self) -> Metadata {
+        let mut meta = self.1.metadata();
+        meta.name = self.0.into();
+        meta
+    }
+
+    fn data(&self) -> Result<Map<Profile, Dict>, figment::Error> {
+        self.1.data()
+    }
+
+    fn profile(&self) -> Option<Profile> {
+        self.1.profile()
+    }
 }
 
 pub fn import(opts: &ImportConfig) -> Result<(), Error> {
@@ -134,3 +474,468 @@ pub fn import(opts: &ImportConfig) -> Result<(), Error> {
         }
     }
 }
+
+/// Names of all config values.
+pub fn config_keys() -> Vec<String> {
+    match serde_json::to_value(Config::default()) {
+        Ok(serde_json::Value::Object(map)) => map.keys().cloned().collect(),
+        _ => unreachable!("config serializes to a map"),
+    }
+}
+
+fn check_key(key: &str) -> Result<(), Error> {
+    if config_keys().iter().any(|k| k == key) {
+        Ok(())
+    } else {
+        Err(Error::NotFound {
+            what: format!(
+                "config value {key}, known values: {}",
+                config_keys().join(", ")
+            ),
+        })
+    }
+}
+
+/// Current value of a config key.
+pub fn get_key(key: &str) -> Result<serde_json::Value, Error> {
+    check_key(key)?;
+    Ok(serde_json::to_value(&*CFG)?[key].take())
+}
+
+/// Set a config value in `config.yaml`, the value is parsed as YAML (falling back to a plain
+/// string) and has to match the type of the key.
+pub fn set_key(key: &str, raw: &str) -> Result<(), Error> {
+    check_key(key)?;
+    let parsed = serde_yaml::from_str(raw).unwrap_or_else(|_| serde_json::Value::from(raw));
+
+    let value = match validate(key, &parsed) {
+        Ok(()) => parsed,
+        // e.g. a section named "2023"
+        Err(err) if !parsed.is_string() => {
+            let string = serde_json::Value::from(raw);
+            validate(key, &string).map_err(|_| err)?;
+            string
+        }
+        Err(err) => return Err(err),
+    };
+    set_key_value(key, value)
+}
+
+/// Set an already parsed config value in `config.yaml`.
+pub fn set_key_value(key: &str, value: serde_json::Value) -> Result<(), Error> {
+    check_key(key)?;
+    validate(key, &value)?;
+    warn_if_overridden(key);
+    // JSON is valid YAML and keeps the value on a single line.
+    let line = format!("{key}: {}", serde_json::to_string(&value)?);
+    edit_config_yaml(key, Some(&line))
+}
+
+/// Remove a config value from `config.yaml` so that the default applies.
+pub fn unset_key(key: &str) -> Result<(), Error> {
+    check_key(key)?;
+    warn_if_overridden(key);
+    edit_config_yaml(key, None)
+}
+
+fn validate(key: &str, value: &serde_json::Value) -> Result<(), Error> {
+    let mut config = serde_json::to_value(Config::default())?;
+    config[key] = value.clone();
+    serde_json::from_value::<Config>(config).map_err(|err| Error::InvalidConfigValue {
+        key: key.into(),
+        message: err.to_string(),
+    })?;
+    Ok(())
+}
+
+fn warn_if_overridden(key: &str) {
+    let var = format!("NEORG_TASK_SYNC_{}", key.to_uppercase());
+    if std::env::var_os(&var).is_some() {
+        log::warn!("{key} is overridden by environment variable {var}");
+    }
+    if let Some(local) = local_config_name() {
+        let sets_key = std::fs::read_to_string(local)
+            .ok()
+            .and_then(|content| serde_yaml::from_str::<serde_yaml::Value>(&content).ok())
+            .is_some_and(|values| values.get(key).is_some());
+        if sets_key {
+            log::warn!("{key} is overridden by local config {}", local.display());
+        }
+    }
+}
+
+fn edit_config_yaml(key: &str, line: Option<&str>) -> Result<(), Error> {
+    let path = config_name();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(handle_load_error(&path, err)),
+    };
+
+    std::fs::create_dir_all(config_dir()).during("creating config folder")?;
+    let tmp = path.with_extension("yaml.new");
+    std::fs::write(&tmp, edit_yaml(&content, key, line)).during("writing config")?;
+    rename(tmp, &path)?;
+    Ok(())
+}
+
+// Replace (or remove) the top-level entry of the key, keeping all other lines including comments.
+// Block values end with their last indented or sequence line before the next top-level key, so
+// blank lines and comments within them are removed, too, and those in front of the next key kept.
+fn edit_yaml(content: &str, key: &str, line: Option<&str>) -> String {
+    let mut lines: Vec<&str> = content.lines().collect();
+    let prefix = format!("{key}:");
+    match lines.iter().position(|l| l.starts_with(&prefix)) {
+        Some(start) => {
+            let is_top_level =
+                |l: &&str| !l.trim().is_empty() && !l.starts_with([' ', '\t', '-', '#']);
+            let is_value = |l: &&str| {
+                l.starts_with([' ', '\t', '-'])
+                    && !l.trim().is_empty()
+                    && !l.trim().starts_with('#')
+            };
+            let len_block = lines[start + 1..]
+                .iter()
+                .take_while(|l| !is_top_level(l))
+                .count();
+            let len_value = lines[start + 1..start + 1 + len_block]
+                .iter()
+                .rposition(is_value)
+                .map_or(0, |last| last + 1);
+            lines.splice(start..start + 1 + len_value, line);
+        }
+        None => lines.extend(line),
+    }
+
+    let mut edited = lines.join("\n");
+    edited.push('\n');
+    edited
+}
+
+/// Effective value of a config key and where it came from.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigSource {
+    #[serde(skip)]
+    pub key: String,
+    pub value: serde_json::Value,
+    pub source: String,
+}
+
+/// Print the effective config, optionally with the source of each value.
+pub fn show(opts: &ShowConfig) -> Result<(), Error> {
+    match (opts.sources, opts.format) {
+        (false, ConfigFormat::Yaml) => print!("{}", serde_yaml::to_string(&*CFG)?),
+        (false, ConfigFormat::Json) => println!("{}", serde_json::to_string_pretty(&*CFG)?),
+        (true, ConfigFormat::Yaml) => {
+            for source in config_sources()? {
+                println!(
+                    "{key}: {value}  # {from}",
+                    key = source.key,
+                    value = serde_json::to_string(&source.value)?,
+                    from = source.source
+                );
+            }
+        }
+        (true, ConfigFormat::Json) => {
+            let sources: serde_json::Map<String, serde_json::Value> = config_sources()?
+                .into_iter()
+                .map(|s| Ok((s.key.clone(), serde_json::to_value(s)?)))
+                .collect::<Result<_, Error>>()?;
+            println!("{}", serde_json::to_string_pretty(&sources)?);
+        }
+    }
+    Ok(())
+}
+
+/// All config values together with the provider that supplied them.
+pub fn config_sources() -> Result<Vec<ConfigSource>, Error> {
+    let figment = Config::figment();
+    let values = serde_json::to_value(&*CFG)?;
+    Ok(config_keys()
+        .into_iter()
+        .map(|key| ConfigSource {
+            value: values[&key].clone(),
+            source: figment
+                .find_metadata(&key)
+                .map(|meta| describe_source(meta, &key))
+                .unwrap_or_else(|| "default".into()),
+            key,
+        })
+        .collect())
+}
+
+fn describe_source(meta: &Metadata, key: &str) -> String {
+    match (meta.name.as_ref(), &meta.source) {
+        // figment names env keys without their prefix
+        (SOURCE_ENV, _) => format!("env {ENV_PREFIX}{}", key.to_ascii_uppercase()),
+        (SOURCE_GLOBAL | SOURCE_LOCAL | SOURCE_FALLBACK, Some(figment::Source::File(path))) => {
+            path.display().to_string()
+        }
+        _ => SOURCE_DEFAULT.into(),
+    }
+}
+
+// The config values documented in the README, whose comments `write_commented` reuses.
+static README_CONFIG: Lazy<&str> = Lazy::new(|| {
+    include_str!("../README.md")
+        .split_once("### Config values (with defaults)\n```yaml\n")
+        .and_then(|(_, rest)| rest.split_once("```"))
+        .map_or("", |(block, _)| block)
+});
+
+// Comment lines directly above the key in the given config example.
+fn key_comment<'a>(example: &'a str, key: &str) -> Vec<&'a str> {
+    let mut comment = Vec::new();
+    for line in example.lines() {
+        if line.starts_with('#') {
+            comment.push(line);
+        } else if line.split_once(':').is_some_and(|(k, _)| k == key) {
+            return comment;
+        } else {
+            comment.clear();
+        }
+    }
+    Vec::new()
+}
+
+/// Create the config folder, returns whether it was missing.
+pub fn create_config_dir() -> Result<bool, Error> {
+    if config_dir().exists() {
+        return Ok(false);
+    }
+    std::fs::create_dir_all(config_dir()).during("creating config folder")?;
+    Ok(true)
+}
+
+pub fn config_exists() -> bool {
+    config_name().exists()
+}
+
+/// Write the complete config into `config.yaml` with a comment for each value, unset values are
+/// commented out. An existing config is kept as backup.
+pub fn write_commented(config: &Config) -> Result<Utf8PathBuf, Error> {
+    let values = serde_json::to_value(config)?;
+    let mut out =
+        String::from("# neorg-task-sync config, see `neorg-task-sync config show --sources`\n");
+    for key in config_keys() {
+        out.push('\n');
+        for line in key_comment(*README_CONFIG, &key) {
+            out.push_str(&format!("{line}\n"));
+        }
+        match values[&key] {
+            serde_json::Value::Null => out.push_str(&format!("# {key}:\n")),
+            ref value => out.push_str(&format!("{key}: {}\n", serde_json::to_string(value)?)),
+        }
+    }
+
+    let path = config_name();
+    if path.exists() {
+        std::fs::copy(&path, path.with_extension("yaml.bak")).during("backing up config")?;
+    }
+    create_config_dir()?;
+    let tmp = path.with_extension("yaml.new");
+    std::fs::write(&tmp, out).during("writing config")?;
+    rename(tmp, &path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    // treeA/.neorg-task-sync.yaml, treeA/sub/ and treeB/ without local config
+    fn trees() -> Result<tempfile::TempDir, Error> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("treeA/sub"))?;
+        fs::create_dir_all(dir.path().join("treeB"))?;
+        fs::write(
+            dir.path().join("treeA").join(LOCAL_CONFIG_NAME),
+            "section_todos: Local\n",
+        )?;
+        fs::write(dir.path().join("treeA/sub/2023-10-18.norg"), "")?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn discover_in_ancestors() -> Result<(), Error> {
+        let dir = trees()?;
+        let want = fs::canonicalize(dir.path().join("treeA"))?.join(LOCAL_CONFIG_NAME);
+
+        let found = local_config_for(dir.path(), &["treeA/sub/2023-10-18.norg", "treeA"])?;
+        assert_eq!(found, Some(want.clone()));
+
+        // no paths: start at the current folder
+        let found = local_config_for(&dir.path().join("treeA/sub"), &[] as &[PathBuf])?;
+        assert_eq!(found, Some(want));
+
+        assert_eq!(local_config_for(dir.path(), &["treeB"])?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn discover_rejects_different_trees() -> Result<(), Error> {
+        let dir = trees()?;
+        let result = local_config_for(dir.path(), &["treeA", "treeB"]);
+        assert!(matches!(result, Err(Error::LocalConfigConflict { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn local_config_whitelist() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let local = dir.path().join(LOCAL_CONFIG_NAME);
+
+        fs::write(&local, "")?;
+        check_local_config(&local)?;
+
+        fs::write(&local, "tasklist: abc\nsection_todos: Work\n")?;
+        check_local_config(&local)?;
+
+        for forbidden in [
+            "client_secret_command: touch /tmp/pwned",
+            "token_store_command: cat",
+            "token_cache_key_file: /tmp/key",
+            "dashboard_file: /tmp/dashboard.norg",
+            "journal_template: /etc/passwd",
+            "workspaces: [/]",
+        ] {
+            fs::write(&local, format!("tasklist: abc\n{forbidden}\n"))?;
+            assert!(
+                matches!(
+                    check_local_config(&local),
+                    Err(Error::InvalidLocalConfig { .. })
+                ),
+                "accepted {forbidden}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn local_config_overrides_global() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let global = Utf8PathBuf::from_path_buf(dir.path().join("config.yaml")).unwrap();
+        let local = dir.path().join(LOCAL_CONFIG_NAME);
+        fs::write(&global, "section_todos: Global\ntasklist: global\n")?;
+        fs::write(&local, "section_todos: Local\n")?;
+
+        let config: Config =
+            Config::figment_with(global, Some(&local), Utf8PathBuf::from("missing.json"))
+                .extract()?;
+        assert_eq!(&*config.section_todos, "Local");
+        assert_eq!(&*config.tasklist, "global");
+        Ok(())
+    }
+
+    #[test]
+    fn source_display() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let global = Utf8PathBuf::from_path_buf(dir.path().join("config.yaml")).unwrap();
+        let local = dir.path().join(LOCAL_CONFIG_NAME);
+        fs::write(&global, "tasklist: global\n")?;
+        fs::write(&local, "section_todos: Local\n")?;
+
+        let figment = Config::figment_with(
+            global.clone(),
+            Some(&local),
+            Utf8PathBuf::from("missing.json"),
+        );
+        let source = |key: &str| {
+            figment
+                .find_metadata(key)
+                .map(|meta| describe_source(meta, key))
+        };
+        assert_eq!(source("tasklist"), Some(global.to_string()));
+        assert_eq!(source("section_todos"), Some(local.display().to_string()));
+        assert_eq!(source("archive_section").as_deref(), Some("default"));
+
+        let env = Tagged(SOURCE_ENV, Env::prefixed(ENV_PREFIX)).metadata();
+        assert_eq!(
+            describe_source(&env, "tasklist"),
+            "env NEORG_TASK_SYNC_TASKLIST"
+        );
+        Ok(())
+    }
+
+    static BLOCK_YAML: &str = r###"# notes
+workspaces:
+  - ~/notes
+
+  # work notes
+  - ~/work
+ignore_filenames:
+- index.norg
+
+# the list to sync
+tasklist: Inbox
+"###;
+
+    #[test]
+    fn edit_block_values() {
+        assert_eq!(
+            edit_yaml(BLOCK_YAML, "workspaces", Some(r#"workspaces: ["~/all"]"#)),
+            r###"# notes
+workspaces: ["~/all"]
+ignore_filenames:
+- index.norg
+
+# the list to sync
+tasklist: Inbox
+"###
+        );
+        assert_eq!(
+            edit_yaml(BLOCK_YAML, "ignore_filenames", None),
+            r###"# notes
+workspaces:
+  - ~/notes
+
+  # work notes
+  - ~/work
+
+# the list to sync
+tasklist: Inbox
+"###
+        );
+        assert_eq!(
+            edit_yaml(BLOCK_YAML, "tasklist", Some("tasklist: Work")),
+            BLOCK_YAML.replace("Inbox", "Work")
+        );
+        assert_eq!(
+            edit_yaml("", "tasklist", Some("tasklist: Work")),
+            "tasklist: Work\n"
+        );
+    }
+
+    #[test]
+    fn config_keys_documented() {
+        for key in config_keys() {
+            assert!(!key_comment(*README_CONFIG, &key).is_empty(), "{key}");
+        }
+        assert_eq!(
+            key_comment("# a\n\n# b\n# c\nkey: 1\n# d\nother: 2\n", "key"),
+            vec!["# b", "# c"]
+        );
+    }
+
+    #[test]
+    fn profile_has_to_exist() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let base = Utf8Path::from_path(dir.path()).unwrap();
+        fs::create_dir_all(base.join(DIR_PROFILES).join("work"))?;
+
+        assert_eq!(&*check_profile(base, "work", false)?, "work");
+        assert!(matches!(
+            check_profile(base, "wrok", false),
+            Err(Error::ProfileNotFound { .. })
+        ));
+        assert_eq!(&*check_profile(base, "private", true)?, "private");
+        assert!(matches!(
+            check_profile(base, "../work", true),
+            Err(Error::InvalidProfile { .. })
+        ));
+        Ok(())
+    }
+}
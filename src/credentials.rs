@@ -0,0 +1,307 @@
+use argon2::Argon2;
+use async_trait::async_trait;
+use camino::Utf8PathBuf;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use google_tasks1::oauth2::storage::{TokenInfo, TokenStorage};
+use google_tasks1::oauth2::{parse_application_secret, read_application_secret, ApplicationSecret};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::cfg::{clientsecret_name, tokencache_encrypted_name, tokencache_name, CFG};
+use crate::error::{handle_load_error, Error, WrapError};
+
+/// Environment variable holding the passphrase to encrypt the token cache with.
+pub const TOKEN_PASSPHRASE_ENV: &str = "NEORG_TASK_SYNC_TOKEN_PASSPHRASE";
+
+/// Entry of the token cache, same format as written by `persist_tokens_to_disk`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedToken {
+    pub scopes: Vec<String>,
+    pub token: TokenInfo,
+}
+
+/// Read the client secret from the configured helper command or the client secret file.
+pub async fn read_client_secret() -> Result<ApplicationSecret, Error> {
+    match CFG.client_secret_command {
+        Some(ref command) => {
+            log::debug!("reading client secret via: {command}");
+            let secret = run_helper(command, &[], None).await?;
+            Ok(parse_application_secret(secret).during("parsing client secret from helper")?)
+        }
+        None => {
+            log::debug!("reading client secret: {}", clientsecret_name());
+            Ok(read_application_secret(clientsecret_name())
+                .await
+                .during("reading clientsecret")?)
+        }
+    }
+}
+
+/// Where tokens are stored if not in the plain token cache file.
+pub enum TokenStore {
+    /// External helper command which is called with one argument:
+    ///
+    /// * `get`: print the stored tokens (nothing if none are stored)
+    /// * `store`: store the tokens read from stdin
+    /// * `erase`: delete the stored tokens
+    Command(Arc<str>),
+
+    /// Token cache file encrypted with a key derived from a passphrase.
+    Encrypted {
+        path: Utf8PathBuf,
+        passphrase: Vec<u8>,
+    },
+}
+
+/// Token store according to the config, `None` if the plain token cache file is used.
+pub fn token_store() -> Result<Option<TokenStore>, Error> {
+    if let Some(ref command) = CFG.token_store_command {
+        return Ok(Some(TokenStore::Command(command.clone())));
+    }
+
+    let passphrase = match (env::var_os(TOKEN_PASSPHRASE_ENV), &CFG.token_cache_key_file) {
+        (Some(passphrase), _) => passphrase.into_encoded_bytes(),
+        (None, Some(key_file)) => fs::read(key_file)
+            .map_err(|err| handle_load_error(key_file, err))?
+            .trim_ascii_end()
+            .to_vec(),
+        (None, None) => return Ok(None),
+    };
+    Ok(Some(TokenStore::Encrypted {
+        path: tokencache_encrypted_name(),
+        passphrase,
+    }))
+}
+
+/// Human readable location of the stored tokens.
+pub fn token_location() -> String {
+    match CFG.token_store_command {
+        Some(ref command) => format!("helper '{command}'"),
+        None if env::var_os(TOKEN_PASSPHRASE_ENV).is_some()
+            || CFG.token_cache_key_file.is_some() =>
+        {
+            tokencache_encrypted_name().to_string()
+        }
+        None => tokencache_name().to_string(),
+    }
+}
+
+/// Load all stored tokens, `None` if nothing is stored.
+pub async fn load_tokens() -> Result<Option<Vec<CachedToken>>, Error> {
+    match token_store()? {
+        Some(store) => store.load().await,
+        None => {
+            let path = tokencache_name();
+            if !path.exists() {
+                return Ok(None);
+            }
+            let content = fs::read(&path).map_err(|err| handle_load_error(&path, err))?;
+            Ok(Some(
+                serde_json::from_slice(&content).during("parsing token cache")?,
+            ))
+        }
+    }
+}
+
+/// Delete all stored tokens.
+pub async fn erase_tokens() -> Result<(), Error> {
+    match token_store()? {
+        Some(TokenStore::Command(command)) => {
+            run_helper(&command, &["erase"], None).await?;
+        }
+        Some(TokenStore::Encrypted { path, .. }) => {
+            fs::remove_file(path).during("deleting encrypted token cache")?
+        }
+        None => fs::remove_file(tokencache_name()).during("deleting token cache")?,
+    }
+    Ok(())
+}
+
+impl TokenStore {
+    async fn load(&self) -> Result<Option<Vec<CachedToken>>, Error> {
+        let stored = match self {
+            Self::Command(command) => run_helper(command, &["get"], None).await?,
+            Self::Encrypted { path, passphrase } => {
+                if !path.exists() {
+                    return Ok(None);
+                }
+                let encrypted = fs::read(path).map_err(|err| handle_load_error(path, err))?;
+                decrypt(passphrase, &encrypted)?
+            }
+        };
+        if stored.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        Ok(Some(
+            serde_json::from_slice(&stored).during("parsing stored tokens")?,
+        ))
+    }
+
+    async fn store(&self, tokens: &[CachedToken]) -> Result<(), Error> {
+        let tokens = serde_json::to_vec(tokens)?;
+        match self {
+            Self::Command(command) => {
+                run_helper(command, &["store"], Some(&tokens)).await?;
+            }
+            Self::Encrypted { path, passphrase } => {
+                if let Some(folder) = path.parent() {
+                    fs::create_dir_all(folder).during("creating folder for token")?;
+                }
+                let tmp = path.with_extension("enc.new");
+                fs::write(&tmp, encrypt(passphrase, &tokens)?)
+                    .during("writing encrypted token cache")?;
+                fs::rename(tmp, path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TokenStorage for TokenStore {
+    async fn set(&self, scopes: &[&str], token: TokenInfo) -> anyhow::Result<()> {
+        let mut tokens = self
+            .load()
+            .await
+            .map_err(|err| anyhow::anyhow!("{err}"))?
+            .unwrap_or_default();
+        tokens.retain(|t| !same_scopes(&t.scopes, scopes));
+        tokens.push(CachedToken {
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            token,
+        });
+        self.store(&tokens[..])
+            .await
+            .map_err(|err| anyhow::anyhow!("{err}"))
+    }
+
+    async fn get(&self, scopes: &[&str]) -> Option<TokenInfo> {
+        let tokens = match self.load().await {
+            Ok(tokens) => tokens?,
+            Err(err) => {
+                log::error!("{err}");
+                return None;
+            }
+        };
+        tokens
+            .into_iter()
+            .find(|t| scopes.iter().all(|s| t.scopes.iter().any(|ts| ts == s)))
+            .map(|t| t.token)
+    }
+}
+
+// Layout of the encrypted token cache: magic, salt, nonce, ciphertext.
+const MAGIC: &[u8] = b"NTSENC1";
+const LEN_SALT: usize = 16;
+const LEN_NONCE: usize = 12;
+
+fn derive_key(passphrase: &[u8], salt: &[u8]) -> Result<Key, Error> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase, salt, key.as_mut_slice())
+        .map_err(|err| Error::Encryption {
+            message: format!("deriving key: {err}").into(),
+        })?;
+    Ok(key)
+}
+
+fn encrypt(passphrase: &[u8], plain: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut salt = [0u8; LEN_SALT];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let encrypted = cipher
+        .encrypt(&nonce, plain)
+        .map_err(|_| Error::Encryption {
+            message: "encrypting token cache failed".into(),
+        })?;
+
+    Ok([MAGIC, &salt[..], &nonce[..], &encrypted[..]].concat())
+}
+
+fn decrypt(passphrase: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::Encryption {
+        message: "token cache is not encrypted by neorg-task-sync".into(),
+    };
+    let data = data.strip_prefix(MAGIC).ok_or_else(invalid)?;
+    if data.len() < LEN_SALT + LEN_NONCE {
+        return Err(invalid());
+    }
+    let (salt, data) = data.split_at(LEN_SALT);
+    let (nonce, encrypted) = data.split_at(LEN_NONCE);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), encrypted)
+        .map_err(|_| Error::Encryption {
+            message: "decrypting token cache failed, wrong passphrase?".into(),
+        })
+}
+
+fn same_scopes(stored: &[String], scopes: &[&str]) -> bool {
+    stored.len() == scopes.len() && scopes.iter().all(|s| stored.iter().any(|ts| ts == s))
+}
+
+// Run the helper command via the shell with the given arguments appended, return its stdout.
+async fn run_helper(command: &str, args: &[&str], stdin: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let helper_error = |message: String| Error::CredentialHelper {
+        command: command.into(),
+        message: message.into(),
+    };
+
+    let mut script = command.to_owned();
+    for arg in args {
+        script.push(' ');
+        script.push_str(arg);
+    }
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&script)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|err| helper_error(format!("failed to start: {err}")))?;
+
+    if let Some(input) = stdin {
+        let mut pipe = child.stdin.take().expect("stdin is piped");
+        pipe.write_all(input).await?;
+        // close stdin so that the helper sees EOF
+        drop(pipe);
+    }
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(helper_error(format!("failed with {}", output.status)));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encryption_roundtrip() -> Result<(), Error> {
+        let plain = br#"[{"scopes":["foo"],"token":{}}]"#;
+        let encrypted = encrypt(b"secret", plain)?;
+        assert!(!encrypted.windows(plain.len()).any(|w| w == plain));
+
+        assert_eq!(decrypt(b"secret", &encrypted)?, plain);
+        assert!(decrypt(b"wrong", &encrypted).is_err());
+        Ok(())
+    }
+}
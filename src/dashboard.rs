@@ -0,0 +1,192 @@
+use chrono::NaiveDate;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::auth;
+use crate::cfg::CFG;
+use crate::error::{Error, WrapError};
+use crate::opts::Dashboard as DashboardOpts;
+use crate::parse::{escape_link_text, norg_link, ParsedNorg, State};
+use crate::sync::workspace_files;
+use crate::tasks::{get_tasks, Task};
+
+/// Header line of generated dashboards, files containing it are never synced.
+pub const DASHBOARD_MARKER: &str =
+    "Generated by neorg-task-sync, manual changes will be overwritten.";
+
+pub async fn perform_dashboard(opts: &DashboardOpts) -> Result<(), Error> {
+    let dashboard = opts
+        .output
+        .clone()
+        .or_else(|| CFG.dashboard_file.clone().map(PathBuf::from))
+        .ok_or_else(|| Error::NotFound {
+            what: "dashboard file (--output or config value: dashboard_file)".into(),
+        })?;
+
+    let files = workspace_files(&opts.files_or_folders[..])?;
+    let tasks = if opts.offline {
+        Vec::new()
+    } else {
+        get_tasks(auth::login().await?, &CFG.tasklist).await?
+    };
+
+    write_dashboard(&dashboard, &files[..], &tasks[..])
+}
+
+/// Regenerate the dashboard listing all open todos, it is only written if it changed.
+pub fn write_dashboard(dashboard: &Path, files: &[PathBuf], tasks: &[Task]) -> Result<(), Error> {
+    let rendered = render(dashboard, files, tasks)?;
+    if fs::read_to_string(dashboard).is_ok_and(|existing| existing == rendered) {
+        log::debug!("dashboard unchanged: {}", dashboard.display());
+        return Ok(());
+    }
+    fs::write(dashboard, rendered).during("writing dashboard")?;
+    log::info!("wrote dashboard: {}", dashboard.display());
+    Ok(())
+}
+
+/// Whether the file was generated as dashboard (judging by its header).
+pub fn is_generated(path: &Path) -> bool {
+    let mut head = [0; 512];
+    let len = match fs::File::open(path).and_then(|mut f| f.read(&mut head)) {
+        Ok(len) => len,
+        Err(_) => return false,
+    };
+    String::from_utf8_lossy(&head[..len]).contains(DASHBOARD_MARKER)
+}
+
+fn render(dashboard: &Path, files: &[PathBuf], tasks: &[Task]) -> Result<String, Error> {
+    let remote: HashMap<Arc<str>, &Task> = tasks.iter().map(|t| (t.id.clone(), t)).collect();
+    let mut present = HashSet::new();
+
+    let mut out = String::new();
+    writeln!(out, "* Open {}", CFG.section_todos)?;
+    writeln!(out, "  {DASHBOARD_MARKER}")?;
+
+    for file in files {
+        let norg = ParsedNorg::open(file)?;
+        present.extend(norg.todos.iter().filter_map(|t| t.id.clone()));
+
+        let open: Vec<_> = norg
+            .todos
+            .iter()
+            .filter(|t| t.state != State::Done)
+            .collect();
+        if open.is_empty() {
+            continue;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "** {}", display_name(dashboard, file))?;
+
+        let mut current_section = None;
+        for todo in open {
+            let section = norg.section_of(todo.line).map(|s| s.title.clone());
+            if section != current_section {
+                writeln!(out, "*** {}", section.as_deref().unwrap_or("(no section)"))?;
+                current_section = section;
+            }
+
+            let due_at = todo.due_at.or_else(|| {
+                todo.id
+                    .as_ref()
+                    .and_then(|id| remote.get(id))
+                    .and_then(|t| t.due_at)
+            });
+            writeln!(
+                out,
+                "    - {link}[{content}]{due}",
+                link = norg_link(dashboard, file, Some(todo.line)),
+                content = escape_link_text(&todo.content),
+                due = format_due(due_at),
+            )?;
+        }
+    }
+
+    let remote_only: Vec<_> = tasks
+        .iter()
+        .filter(|t| !t.completed && !present.contains(&t.id))
+        .collect();
+    if !remote_only.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "** Remote only")?;
+        for task in remote_only {
+            writeln!(
+                out,
+                "    - {title}{due}",
+                title = task.title,
+                due = format_due(task.due_at)
+            )?;
+        }
+    }
+
+    Ok(out)
+}
+
+fn format_due(due_at: Option<NaiveDate>) -> String {
+    due_at
+        .map(|d| format!(" (due {})", d.format("%Y-%m-%d")))
+        .unwrap_or_default()
+}
+
+// Name of the file relative to the dashboard, if possible.
+fn display_name(dashboard: &Path, file: &Path) -> String {
+    dashboard
+        .parent()
+        .and_then(|folder| file.strip_prefix(folder).ok())
+        .unwrap_or(file)
+        .with_extension("")
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::Local;
+    use pretty_assertions::assert_eq;
+
+    fn task(id: &str, title: &str, due_at: Option<NaiveDate>) -> Task {
+        Task {
+            completed: false,
+            id: id.into(),
+            title: title.into(),
+            modified_at: Local::now(),
+            due_at,
+            completed_at: None,
+        }
+    }
+
+    #[test]
+    fn render_open_todos() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("2023-10-18.norg");
+        fs::write(
+            &file,
+            "* TODOs\n  - ( ) Read ] docs %#taskid id1%\n  - (x) Done %#taskid id2%\n",
+        )?;
+        let dashboard = dir.path().join("dashboard.norg");
+        let due = NaiveDate::from_ymd_opt(2023, 10, 20);
+        let tasks = [
+            task("id1", "Read ] docs", due),
+            task("id2", "Done", None),
+            task("id3", "Remote", None),
+        ];
+
+        let want = format!(
+            "* Open {section}\n  {DASHBOARD_MARKER}\n\n** 2023-10-18\n*** TODOs\n    - {{:2023-10-18:2}}[Read \\] docs] (due 2023-10-20)\n\n** Remote only\n    - Remote\n",
+            section = CFG.section_todos
+        );
+        write_dashboard(&dashboard, &[file.clone()], &tasks)?;
+        assert_eq!(fs::read_to_string(&dashboard)?, want);
+
+        assert!(is_generated(&dashboard));
+        assert!(!is_generated(&file));
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+use chrono::{Local, TimeZone};
+use console::style;
+use std::fs;
+use std::path::Path;
+
+use crate::auth::{self, token_info, TokenInfo, SCOPES};
+use crate::cfg::{
+    clientsecret_name, local_config_name, state_name, Config, CFG, LOCAL_CONFIG_KEYS,
+    LOCAL_CONFIG_NAME,
+};
+use crate::credentials::{load_tokens, read_client_secret, token_location};
+use crate::error::Error;
+use crate::parse::{backup_dir, check_query};
+use crate::tasks::get_tasklists;
+
+/// Result of a single check.
+enum Check {
+    Pass(String),
+    Fail { what: String, remedy: String },
+    Skip(String),
+}
+
+#[derive(Default)]
+struct Doctor {
+    num_failed: usize,
+}
+
+impl Doctor {
+    fn report(&mut self, name: &str, check: Check) {
+        match check {
+            Check::Pass(what) => println!("{} {name}: {what}", style("✓").bold().green()),
+            Check::Fail { what, remedy } => {
+                self.num_failed += 1;
+                println!("{} {name}: {what}", style("✗").bold().red());
+                println!("    {} {remedy}", style("→").bold());
+            }
+            Check::Skip(why) => println!("{} {name}: skipped, {why}", style("-").bold().dim()),
+        }
+    }
+}
+
+fn fail(what: impl ToString, remedy: impl ToString) -> Check {
+    Check::Fail {
+        what: what.to_string(),
+        remedy: remedy.to_string(),
+    }
+}
+
+/// Check the whole setup and print a line (with remedy on failure) for each check. The local
+/// config is discovered before, a broken one is passed in to be reported.
+pub async fn perform_doctor(local_config: Result<(), Error>) -> Result<(), Error> {
+    let mut doctor = Doctor::default();
+
+    doctor.report("local config", check_local_config(local_config));
+
+    // Everything else reads the global config, which panics if it doesn't load.
+    let config_ok = match Config::load() {
+        Ok(_) => {
+            doctor.report("config", Check::Pass("parses".into()));
+            true
+        }
+        Err(err) => {
+            doctor.report(
+                "config",
+                fail(
+                    err,
+                    "fix the reported value, see `neorg-task-sync config show --sources`",
+                ),
+            );
+            false
+        }
+    };
+
+    doctor.report("tree-sitter query", check_tree_sitter());
+    doctor.report("backup folder", check_writable(&backup_dir()));
+    if let Some(cache) = state_name().parent() {
+        doctor.report("cache folder", check_writable(cache.as_std_path()));
+    }
+
+    if !config_ok {
+        for name in ["client secret", "token", "tasklist", "workspaces"] {
+            doctor.report(name, Check::Skip("config does not load".into()));
+        }
+        return finish(doctor);
+    }
+
+    doctor.report("client secret", check_client_secret().await);
+    let token = check_token().await;
+    let logged_in = matches!(token, Check::Pass(_));
+    doctor.report("token", token);
+    if logged_in {
+        doctor.report("tasklist", check_tasklist().await);
+    } else {
+        doctor.report("tasklist", Check::Skip("not logged in".into()));
+    }
+    doctor.report("workspaces", check_workspaces());
+
+    finish(doctor)
+}
+
+fn finish(doctor: Doctor) -> Result<(), Error> {
+    match doctor.num_failed {
+        0 => Ok(()),
+        num => Err(Error::ChecksFailed { num }),
+    }
+}
+
+fn check_local_config(discovered: Result<(), Error>) -> Check {
+    match discovered {
+        Ok(()) => match local_config_name() {
+            Some(path) => Check::Pass(format!("{} is valid", path.display())),
+            None => Check::Skip("none found".into()),
+        },
+        Err(err) => fail(
+            err,
+            format!(
+                "fix or remove the local config ({LOCAL_CONFIG_NAME}), it may only set: {}",
+                LOCAL_CONFIG_KEYS.join(", ")
+            ),
+        ),
+    }
+}
+
+fn check_tree_sitter() -> Check {
+    match check_query() {
+        Ok(()) => Check::Pass("norg query compiles".into()),
+        Err(err) => fail(
+            err,
+            "rebuild neorg-task-sync, the bundled tree-sitter-norg grammar does not match",
+        ),
+    }
+}
+
+// Checks the folder, or the nearest existing folder above it that it would be created in, without
+// creating anything.
+fn check_writable(folder: &Path) -> Check {
+    let Some(existing) = folder.ancestors().find(|a| a.exists()) else {
+        return fail(
+            format!("no existing folder above {}", folder.display()),
+            "fix the configured folders",
+        );
+    };
+    let writable = fs::metadata(existing)
+        .map(|m| m.is_dir() && !m.permissions().readonly())
+        .unwrap_or(false);
+    match (writable, existing == folder) {
+        (true, true) => Check::Pass(format!("{} is writable", folder.display())),
+        (true, false) => Check::Pass(format!(
+            "{} can be created in {}",
+            folder.display(),
+            existing.display()
+        )),
+        (false, _) => fail(
+            format!("cannot write to {}", existing.display()),
+            "fix the permissions of the folder",
+        ),
+    }
+}
+
+async fn check_client_secret() -> Check {
+    let remedy = match CFG.client_secret_command {
+        Some(ref command) => format!("check that `{command}` prints the client secret json"),
+        None => format!(
+            "download the OAuth client json (see README) and run `neorg-task-sync config import client-secret -f <file>` to place it at {}",
+            clientsecret_name()
+        ),
+    };
+    match read_client_secret().await {
+        Ok(_) => Check::Pass("exists and parses".into()),
+        Err(err) => fail(err, remedy),
+    }
+}
+
+async fn check_token() -> Check {
+    let tokens = match load_tokens().await {
+        Ok(Some(tokens)) => tokens,
+        Ok(None) => {
+            return fail(
+                format!("no token in {}", token_location()),
+                "run `neorg-task-sync auth login`",
+            )
+        }
+        Err(err) => {
+            return fail(
+                err,
+                "run `neorg-task-sync auth logout --local` and log in again",
+            )
+        }
+    };
+
+    let Some(cached) = tokens
+        .into_iter()
+        .find(|t| SCOPES.iter().all(|s| t.scopes.iter().any(|ts| ts == s)))
+    else {
+        return fail(
+            "no token with all required scopes",
+            "run `neorg-task-sync auth login` and grant access to google tasks",
+        );
+    };
+
+    if cached.token.refresh_token.is_none() {
+        return fail(
+            "token cannot be refreshed",
+            "run `neorg-task-sync auth logout` and log in again",
+        );
+    }
+
+    let expires_at = cached
+        .token
+        .expires_at
+        .and_then(|t| Local.timestamp_opt(t.unix_timestamp(), 0).single());
+    match (cached.token.access_token, expires_at) {
+        (Some(access_token), Some(expires_at)) if expires_at > Local::now() => {
+            match token_info(&access_token).await {
+                Ok(TokenInfo {
+                    accepted: true,
+                    email,
+                }) => Check::Pass(format!(
+                    "valid until {}{}",
+                    expires_at.format("%Y-%m-%d %H:%M"),
+                    email.map_or(String::new(), |email| format!(" ({email})"))
+                )),
+                Ok(TokenInfo {
+                    accepted: false, ..
+                }) => fail(
+                    "access token was rejected, it might have been revoked",
+                    "run `neorg-task-sync auth logout --local` and log in again",
+                ),
+                Err(err) => fail(err, "check your internet connection"),
+            }
+        }
+        _ => Check::Pass("expired, but will be refreshed on next use".into()),
+    }
+}
+
+async fn check_tasklist() -> Check {
+    if CFG.tasklist.is_empty() {
+        return fail(
+            "no tasklist configured",
+            "run `neorg-task-sync config tasklist set`",
+        );
+    }
+    let tasklists = match auth::login().await {
+        Ok(auth) => get_tasklists(auth).await,
+        Err(err) => Err(err),
+    };
+    match tasklists {
+        Ok(tasklists) => match tasklists.iter().find(|tl| tl.id == CFG.tasklist) {
+            Some(tl) => Check::Pass(format!("{} exists", tl.title)),
+            None => fail(
+                format!(
+                    "configured tasklist {} does not exist remotely",
+                    CFG.tasklist
+                ),
+                "run `neorg-task-sync config tasklist set` to select an existing one",
+            ),
+        },
+        Err(err) => fail(err, "check your internet connection and login"),
+    }
+}
+
+fn check_workspaces() -> Check {
+    if CFG.workspaces.is_empty() {
+        return Check::Skip("none configured".into());
+    }
+    let unreachable: Vec<_> = CFG
+        .workspaces
+        .iter()
+        .filter(|w| fs::metadata(w).is_err())
+        .map(|w| w.to_string())
+        .collect();
+    if unreachable.is_empty() {
+        Check::Pass(format!("{} reachable", CFG.workspaces.len()))
+    } else {
+        fail(
+            format!("not reachable: {}", unreachable.join(", ")),
+            "fix the paths via `neorg-task-sync config set workspaces '[...]'`",
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn describe(check: Check) -> String {
+        match check {
+            Check::Pass(what) => format!("pass: {what}"),
+            Check::Fail { what, .. } => format!("fail: {what}"),
+            Check::Skip(why) => format!("skip: {why}"),
+        }
+    }
+
+    #[test]
+    fn writable_without_creating() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        assert_eq!(
+            describe(check_writable(dir.path())),
+            format!("pass: {} is writable", dir.path().display())
+        );
+
+        let missing = dir.path().join("backup/nested");
+        assert_eq!(
+            describe(check_writable(&missing)),
+            format!(
+                "pass: {} can be created in {}",
+                missing.display(),
+                dir.path().display()
+            )
+        );
+        assert!(!dir.path().join("backup").exists());
+
+        let file = dir.path().join("file");
+        fs::write(&file, b"")?;
+        assert_eq!(
+            describe(check_writable(&file.join("below"))),
+            format!("fail: cannot write to {}", file.display())
+        );
+
+        let readonly = dir.path().join("readonly");
+        fs::create_dir(&readonly)?;
+        let mut permissions = fs::metadata(&readonly)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&readonly, permissions)?;
+        assert_eq!(
+            describe(check_writable(&readonly.join("cache"))),
+            format!("fail: cannot write to {}", readonly.display())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn local_config_check() {
+        assert_eq!(describe(check_local_config(Ok(()))), "skip: none found");
+        assert_eq!(
+            describe(check_local_config(Err(Error::InvalidLocalConfig {
+                path: "notes/.neorg-task-sync.yaml".into(),
+                message: "expected config values".into(),
+            }))),
+            "fail: invalid local config notes/.neorg-task-sync.yaml: expected config values"
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+use crate::error::Error;
+
+/// Open the file in `$VISUAL` or `$EDITOR` (falling back to `vi`) at the given 0-based line.
+pub fn open_in_editor(file: &Path, line: usize) -> Result<(), Error> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+    let editor_error = |message: String| Error::Editor {
+        command: editor.as_str().into(),
+        message: message.into(),
+    };
+
+    // the editor may contain arguments itself, e.g. `code --wait`
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg("sh")
+        // norg line numbers are 1-based
+        .arg(format!("+{}", line + 1))
+        .arg(file)
+        .status()
+        .map_err(|err| editor_error(format!("failed to start: {err}")))?;
+
+    if !status.success() {
+        return Err(editor_error(format!("failed with {status}")));
+    }
+    Ok(())
+}
@@ -20,6 +20,15 @@ pub enum Error {
     },
 
     // INDIVIDUAL ERRORS
+    #[error("{num} check(s) failed")]
+    ChecksFailed { num: usize },
+
+    #[error("credential helper '{command}': {message}")]
+    CredentialHelper {
+        command: Arc<str>,
+        message: Arc<str>,
+    },
+
     #[error("error logging in: {message}")]
     Login { message: Arc<str> },
 
@@ -29,21 +38,54 @@ pub enum Error {
         source: Box<figment::Error>,
     },
 
+    #[error("formatting: {source}")]
+    Format {
+        #[from]
+        source: std::fmt::Error,
+    },
+
+    #[error("editor '{command}': {message}")]
+    Editor {
+        command: Arc<str>,
+        message: Arc<str>,
+    },
+
+    #[error("token cache encryption: {message}")]
+    Encryption { message: Arc<str> },
+
     #[error("invalid file extension: {ext}")]
     InvalidFileExtension { ext: String },
 
+    #[error("http: {source}")]
+    Http {
+        #[from]
+        source: hyper::Error,
+    },
+
+    #[error("invalid value for config {key}: {message}")]
+    InvalidConfigValue { key: String, message: String },
+
+    #[error("invalid local config {path}: {message}")]
+    InvalidLocalConfig { path: String, message: String },
+
+    #[error("invalid profile name: {name}")]
+    InvalidProfile { name: String },
+
     #[error("i/o: {source}")]
     IO {
         #[from]
         source: io::Error,
     },
 
+    #[error("files or folders use different local configs: {first} and {second}")]
+    LocalConfigConflict { first: String, second: String },
+
+    #[error("found {num} problem(s)")]
+    LintFailed { num: usize },
+
     #[error("nothing supplied to stdin")]
     NoStdin,
 
-    #[error("found no tasks")]
-    NoTasks,
-
     #[error("not found: {what}")]
     NotFound { what: String },
 
@@ -65,18 +107,60 @@ pub enum Error {
         source: google_tasks1::oauth2::Error,
     },
 
+    #[error("msgpack-rpc: decoding message: {source}")]
+    RpcDecode {
+        #[from]
+        source: rmpv::decode::Error,
+    },
+
+    #[error("msgpack-rpc: encoding message: {source}")]
+    RpcEncode {
+        #[from]
+        source: rmpv::encode::Error,
+    },
+
+    #[error("msgpack-rpc: converting value: {source}")]
+    RpcValue {
+        #[from]
+        source: rmpv::ext::Error,
+    },
+
+    #[error("invalid regex: {source}")]
+    Regex {
+        #[from]
+        source: regex::Error,
+    },
+
+    #[error("profile already exists: {name}")]
+    ProfileExists { name: String },
+
+    #[error("profile not found: {name} (create it via `config profile create {name}`)")]
+    ProfileNotFound { name: String },
+
+    #[error("revoking token: {message}")]
+    Revoke { message: Arc<str> },
+
     #[error("failed to parse JSON: {source}")]
     SerdeJSON {
         #[from]
         source: serde_json::Error,
     },
 
+    #[error("failed to serialize YAML: {source}")]
+    SerdeYAML {
+        #[from]
+        source: serde_yaml::Error,
+    },
+
     #[error("google tasks api: {source}")]
     TasksAPI {
         #[from]
         source: Box<google_tasks1::Error>,
     },
 
+    #[error("sync failed with {num} error(s)")]
+    SyncFailed { num: usize },
+
     #[error("cannot sync todo without id: {content}")]
     TodoNoID { content: String },
 
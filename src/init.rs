@@ -0,0 +1,144 @@
+use camino::Utf8PathBuf;
+use console::style;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::auth::{self, LoginFlow};
+use crate::cfg::{self, clientsecret_name, Config};
+use crate::credentials::load_tokens;
+use crate::error::{Error, WrapError};
+use crate::opts::{ImportConfig, ImportTarget};
+use crate::parse::ParsedNorg;
+use crate::select::{confirm, prompt, select_plain_single};
+use crate::sync::get_files_from_folders;
+use crate::tasks::get_tasklists;
+
+/// Walk through all setup steps, skipping those that are already done.
+pub async fn perform_init() -> Result<(), Error> {
+    // only what is (to be) stored in config.yaml, not the environment or a local config
+    let mut config = Config::load_global()?;
+
+    // config folder
+    if cfg::create_config_dir()? {
+        step_done("created config folder");
+    } else {
+        step_done("config folder exists");
+    }
+
+    // client secret
+    if config.client_secret_command.is_some() || clientsecret_name().exists() {
+        step_done("client secret available");
+    } else {
+        step_todo("import client secret (see README on how to create one)");
+        let path = prompt("Path of the downloaded client secret json", None)?;
+        cfg::import(&ImportConfig {
+            what: ImportTarget::ClientSecret,
+            file: Some(Utf8PathBuf::from(path)),
+        })?;
+        step_done("imported client secret");
+    }
+
+    // login
+    let logged_in = load_tokens().await?.is_some();
+    if logged_in {
+        step_done("logged in");
+    } else {
+        step_todo("log in via browser");
+    }
+
+    // tasklist, only logging in if it has to be selected
+    if !logged_in || config.tasklist.is_empty() {
+        let auth = auth::login_with(LoginFlow {
+            account: true,
+            ..LoginFlow::default()
+        })
+        .await
+        .during("logging in")?;
+        if !logged_in {
+            step_done("logged in");
+        }
+        let tasklists = get_tasklists(auth).await?;
+        match tasklists.iter().find(|tl| tl.id == config.tasklist) {
+            Some(tl) => step_done(&format!("tasklist: {}", tl.title)),
+            None => {
+                step_todo("select tasklist to sync to");
+                let tl = select_plain_single(tasklists).ok_or_else(|| Error::NotFound {
+                    what: "tasklist selection".into(),
+                })?;
+                step_done(&format!("tasklist: {}", tl.title));
+                config.tasklist = tl.id;
+            }
+        }
+    } else {
+        step_done(&format!("tasklist: {}", config.tasklist));
+    }
+
+    // journal
+    let journal = match config.workspaces.first() {
+        Some(journal) => {
+            step_done(&format!("journal: {journal}"));
+            journal.clone()
+        }
+        None => {
+            step_todo("set folder containing the norg journal");
+            let journal = Utf8PathBuf::from(prompt("Journal folder", Some("."))?);
+            let journal = journal.canonicalize_utf8().unwrap_or(journal);
+            config.workspaces = vec![journal.clone()];
+            journal
+        }
+    };
+
+    // section, done if the journal already uses it
+    let sections = journal_sections(journal.as_std_path(), &config)?;
+    if sections.contains(&config.section_todos) {
+        step_done(&format!("section: {}", config.section_todos));
+    } else {
+        step_todo("select section to sync todos to");
+        let candidates: Vec<String> = std::iter::once(config.section_todos.clone())
+            .chain(sections)
+            .map(|s| s.to_string())
+            .collect();
+        if let Some(section) = select_plain_single(candidates) {
+            config.section_todos = section.into();
+        }
+        step_done(&format!("section: {}", config.section_todos));
+    }
+
+    // config file
+    if cfg::config_exists() && !confirm("Overwrite config.yaml (a backup is kept)?")? {
+        println!("kept config.yaml unchanged");
+        return Ok(());
+    }
+    let path = cfg::write_commented(&config)?;
+    step_done(&format!("wrote {path}"));
+
+    println!();
+    println!("Run your first sync via: neorg-task-sync sync {journal}");
+    Ok(())
+}
+
+// All headings found in the journal.
+fn journal_sections(journal: &std::path::Path, config: &Config) -> Result<Vec<Arc<str>>, Error> {
+    let mut sections: Vec<Arc<str>> = Vec::new();
+    let files: Vec<PathBuf> = if journal.exists() {
+        get_files_from_folders(&[journal], &config.ignore_filenames[..])?
+    } else {
+        Vec::new()
+    };
+    for file in files {
+        for section in ParsedNorg::open(&file)?.sections {
+            if !sections.contains(&section.title) {
+                sections.push(section.title);
+            }
+        }
+    }
+    Ok(sections)
+}
+
+fn step_done(what: &str) {
+    println!("{} {what}", style("✓").bold().green());
+}
+
+fn step_todo(what: &str) {
+    println!("{} {what}", style("→").bold().cyan());
+}
@@ -0,0 +1,83 @@
+use chrono::{Local, NaiveDate};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cfg::{JournalLayout, CFG};
+use crate::error::{handle_load_error, Error, WrapError};
+
+// Used if no template is configured. Placeholders: {date}, {section_todos}
+const DEFAULT_TEMPLATE: &str = "* {section_todos}\n";
+
+/// Path of the journal file governing the given day.
+pub fn journal_file(journal: &Path, day: NaiveDate) -> PathBuf {
+    journal_file_in(CFG.journal_layout, journal, day)
+}
+
+fn journal_file_in(layout: JournalLayout, journal: &Path, day: NaiveDate) -> PathBuf {
+    match layout {
+        JournalLayout::Flat => journal.join(format!("{}.norg", day.format("%Y-%m-%d"))),
+        JournalLayout::Nested => journal.join(format!("{}.norg", day.format("%Y/%m/%d"))),
+    }
+}
+
+/// Return the journal file for today, creating it from the template if it does not exist.
+pub fn create_today(journal: &Path) -> Result<PathBuf, Error> {
+    let today = Local::now().date_naive();
+    let file = journal_file(journal, today);
+    if file.exists() {
+        return Ok(file);
+    }
+
+    if let Some(folder) = file.parent() {
+        fs::create_dir_all(folder).during("creating journal folder")?;
+    }
+    fs::write(&file, render_template(today)?).during("creating journal file for today")?;
+    log::info!("created journal file: {}", file.display());
+
+    Ok(file)
+}
+
+fn render_template(day: NaiveDate) -> Result<String, Error> {
+    let template = match CFG.journal_template {
+        Some(ref path) => fs::read_to_string(path).map_err(|err| handle_load_error(path, err))?,
+        None => DEFAULT_TEMPLATE.into(),
+    };
+    Ok(template
+        .replace("{date}", &day.format("%Y-%m-%d").to_string())
+        .replace("{section_todos}", &CFG.section_todos))
+}
+
+/// Folder containing the journal files, i.e. the first folder specified.
+pub fn journal_folder<P: AsRef<Path>>(files_or_folders: &[P]) -> Result<PathBuf, Error> {
+    files_or_folders
+        .iter()
+        .map(|p| p.as_ref())
+        .find(|p| p.is_dir())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| Error::NotFound {
+            what: "journal folder to create today's file in".into(),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::parse::day_of_path;
+
+    #[test]
+    fn journal_layouts() -> Result<(), Error> {
+        let day = NaiveDate::from_ymd_opt(2023, 10, 8).unwrap();
+        for (layout, want) in [
+            (JournalLayout::Flat, "journal/2023-10-08.norg"),
+            (JournalLayout::Nested, "journal/2023/10/08.norg"),
+        ] {
+            let file = journal_file_in(layout, Path::new("journal"), day);
+            assert_eq!(file, PathBuf::from(want));
+            assert_eq!(day_of_path(&file, layout)?, day);
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,30 @@
+mod agenda;
+mod archive;
 mod auth;
+mod capture;
 mod cfg;
+mod credentials;
+mod dashboard;
+mod doctor;
+mod editor;
 mod error;
+mod init;
+mod journal;
+mod lint;
+mod nvim;
+mod open;
 mod opts;
 mod parse;
 mod progress_bar;
+mod report;
+mod rollover;
 mod run;
+mod search;
 mod select;
+mod state;
 mod sync;
+mod task_commands;
+mod tasklist;
 mod tasks;
 
 pub use error::Error;
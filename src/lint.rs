@@ -0,0 +1,297 @@
+use console::style;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::auth;
+use crate::cfg::CFG;
+use crate::error::Error;
+use crate::opts::Lint as LintOpts;
+use crate::parse::{MangledId, ParsedNorg};
+use crate::sync::workspace_files;
+use crate::tasks::{get_tasklists, get_tasks};
+
+// Locations (file and line) of each task id.
+type Locations = HashMap<Arc<str>, Vec<(PathBuf, usize)>>;
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{file}:{line}: {message}",
+            file = self.file.display(),
+            // norg line numbers are 1-based
+            line = self.line + 1,
+            message = self.message,
+        )
+    }
+}
+
+pub async fn perform_lint(opts: &LintOpts) -> Result<(), Error> {
+    let files = workspace_files(&opts.files_or_folders[..])?;
+
+    let mut problems = Vec::new();
+    let mut locations = Locations::new();
+    let mut num_fixed = 0;
+
+    for file in &files {
+        let mut norg = ParsedNorg::open(file)?;
+        for todo in &norg.todos {
+            if let Some(ref id) = todo.id {
+                locations
+                    .entry(id.clone())
+                    .or_default()
+                    .push((file.clone(), todo.line));
+            }
+        }
+
+        let mangled = norg.mangled_ids();
+        let (fixable, unfixable): (Vec<_>, Vec<_>) = mangled
+            .into_iter()
+            .partition(|m| opts.fix && m.id.is_some());
+
+        for m in unfixable {
+            problems.push(Problem {
+                file: file.clone(),
+                line: m.line,
+                message: match m.id {
+                    Some(id) => format!("mangled task id comment (fixable: {id})"),
+                    None => "mangled task id comment without id".into(),
+                },
+            });
+        }
+
+        if !fixable.is_empty() {
+            fix_mangled(&mut norg, &fixable[..])?;
+            for m in &fixable {
+                if let Some(ref id) = m.id {
+                    locations
+                        .entry(id.clone())
+                        .or_default()
+                        .push((file.clone(), m.line));
+                }
+            }
+            norg.backup()?;
+            norg.write()?;
+            num_fixed += fixable.len();
+        }
+    }
+
+    problems.extend(duplicates(&locations));
+
+    if !opts.offline {
+        problems.extend(check_remote(&locations).await?);
+    }
+
+    problems.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    for problem in &problems {
+        println!("{problem}");
+    }
+    if num_fixed > 0 {
+        println!(
+            "{} {num_fixed} mangled task id(s)",
+            style("fixed").bold().green()
+        );
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        num => Err(Error::LintFailed { num }),
+    }
+}
+
+// Rewrite the comments of mangled ids that could be recovered to proper task id comments.
+fn fix_mangled(norg: &mut ParsedNorg, fixable: &[MangledId]) -> Result<(), Error> {
+    // the comment is rewritten in place, ranges of other lines stay valid
+    let mut lines = norg.lines();
+    for m in fixable {
+        let Some(ref id) = m.id else { continue };
+        let comment = format!("%#taskid {id}%");
+        lines[m.line].splice(m.in_line.clone(), comment.into_bytes());
+    }
+    norg.set_lines(&lines[..])
+}
+
+// A problem at each location of an id that occurs more than once.
+fn duplicates(locations: &Locations) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for (id, locations) in locations.iter().filter(|(_, l)| l.len() > 1) {
+        for (file, line) in locations {
+            let others: Vec<_> = locations
+                .iter()
+                .filter(|(f, l)| f != file || l != line)
+                .map(|(f, l)| format!("{}:{}", f.display(), l + 1))
+                .collect();
+            problems.push(Problem {
+                file: file.clone(),
+                line: *line,
+                message: format!("duplicate task id {id}, also at {}", others.join(", ")),
+            });
+        }
+    }
+    problems
+}
+
+// Ids that are not part of the configured tasklist, looked up in all other tasklists.
+async fn check_remote(locations: &Locations) -> Result<Vec<Problem>, Error> {
+    let auth = auth::login().await?;
+    let known: HashSet<Arc<str>> = get_tasks(auth.clone(), &CFG.tasklist)
+        .await?
+        .into_iter()
+        .map(|t| t.id)
+        .collect();
+
+    let unknown = unknown_ids(locations, &known);
+    if unknown.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut tasklist_of = HashMap::new();
+    for tasklist in get_tasklists(auth.clone()).await? {
+        if tasklist.id == CFG.tasklist {
+            continue;
+        }
+        for task in get_tasks(auth.clone(), &tasklist.id).await? {
+            tasklist_of.insert(task.id, tasklist.title.clone());
+        }
+    }
+
+    Ok(classify_unknown(locations, &unknown[..], &tasklist_of))
+}
+
+// Ids not in the configured tasklist, sorted.
+fn unknown_ids<'a>(locations: &'a Locations, known: &HashSet<Arc<str>>) -> Vec<&'a Arc<str>> {
+    let mut unknown: Vec<&Arc<str>> = locations.keys().filter(|id| !known.contains(*id)).collect();
+    unknown.sort();
+    unknown
+}
+
+// A problem at each location of the unknown ids, naming the tasklist they belong to if any.
+fn classify_unknown(
+    locations: &Locations,
+    unknown: &[&Arc<str>],
+    tasklist_of: &HashMap<Arc<str>, Arc<str>>,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for id in unknown {
+        let message = match tasklist_of.get(*id) {
+            Some(title) => format!("task id {id} belongs to tasklist {title}"),
+            None => format!("task id {id} not found in any tasklist"),
+        };
+        for (file, line) in &locations[*id] {
+            problems.push(Problem {
+                file: file.clone(),
+                line: *line,
+                message: message.clone(),
+            });
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::Local;
+    use pretty_assertions::assert_eq;
+    use std::path::Path;
+
+    static LINT_GIVEN: &str = r###"* TODOs
+  - ( ) Fine %#taskid foobar1%
+  - ( ) Space after hash %# taskid foobar2%
+  - ( ) Without id %# taskid%
+  - ( ) Without hash %task id MTIzNDU2Nzg5MDEyMzQ1Njc4OTA%
+"###;
+
+    static LINT_WANT: &str = r###"* TODOs
+  - ( ) Fine %#taskid foobar1%
+  - ( ) Space after hash %#taskid foobar2%
+  - ( ) Without id %# taskid%
+  - ( ) Without hash %#taskid MTIzNDU2Nzg5MDEyMzQ1Njc4OTA%
+"###;
+
+    fn locations(entries: &[(&str, &str, usize)]) -> Locations {
+        let mut locations = Locations::new();
+        for (id, file, line) in entries {
+            locations
+                .entry((*id).into())
+                .or_default()
+                .push((PathBuf::from(file), *line));
+        }
+        locations
+    }
+
+    fn describe(mut problems: Vec<Problem>) -> Vec<String> {
+        problems.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+        problems.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn fix_mangled_ids() -> Result<(), Error> {
+        let mut norg = ParsedNorg::from_source(
+            Path::new("lint.norg"),
+            LINT_GIVEN.as_bytes().to_vec(),
+            Local::now(),
+        )?;
+        let fixable: Vec<MangledId> = norg
+            .mangled_ids()
+            .into_iter()
+            .filter(|m| m.id.is_some())
+            .collect();
+        assert_eq!(fixable.len(), 2);
+
+        fix_mangled(&mut norg, &fixable[..])?;
+        assert_eq!(String::from_utf8_lossy(norg.source_code()), LINT_WANT);
+
+        let ids: Vec<&str> = norg.todos.iter().filter_map(|t| t.id.as_deref()).collect();
+        assert_eq!(ids, ["foobar1", "foobar2", "MTIzNDU2Nzg5MDEyMzQ1Njc4OTA"]);
+        assert_eq!(norg.mangled_ids().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let locations = locations(&[
+            ("foobar1", "a.norg", 1),
+            ("foobar2", "a.norg", 2),
+            ("foobar1", "b.norg", 4),
+        ]);
+        assert_eq!(
+            describe(duplicates(&locations)),
+            [
+                "a.norg:2: duplicate task id foobar1, also at b.norg:5",
+                "b.norg:5: duplicate task id foobar1, also at a.norg:2",
+            ]
+        );
+    }
+
+    #[test]
+    fn classify_remote_ids() {
+        let locations = locations(&[
+            ("known", "a.norg", 1),
+            ("elsewhere", "a.norg", 2),
+            ("gone", "b.norg", 3),
+        ]);
+        let known = HashSet::from([Arc::from("known")]);
+        let unknown = unknown_ids(&locations, &known);
+        let names: Vec<&str> = unknown.iter().map(|id| id.as_ref()).collect();
+        assert_eq!(names, ["elsewhere", "gone"]);
+
+        let tasklist_of = HashMap::from([(Arc::from("elsewhere"), Arc::from("Work"))]);
+        assert_eq!(
+            describe(classify_unknown(&locations, &unknown[..], &tasklist_of)),
+            [
+                "a.norg:3: task id elsewhere belongs to tasklist Work",
+                "b.norg:4: task id gone not found in any tasklist",
+            ]
+        );
+    }
+}
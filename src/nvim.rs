@@ -0,0 +1,340 @@
+use chrono::{DateTime, Local, NaiveDate};
+use rmpv::Value;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+use crate::auth;
+use crate::auth::{Authenticator, LoginFlow};
+use crate::cfg::CFG;
+use crate::error::{Error, WrapError};
+use crate::parse::{ParsedNorg, State, Todo};
+use crate::sync::Syncer;
+use crate::tasks::{get_tasks, task_complete};
+
+// msgpack-RPC message types, see: https://github.com/msgpack-rpc/msgpack-rpc/blob/master/spec.md
+const MSG_REQUEST: u64 = 0;
+const MSG_RESPONSE: u64 = 1;
+const MSG_NOTIFICATION: u64 = 2;
+
+/// Contents of a neovim buffer as sent by the editor.
+#[derive(Debug, Clone, Deserialize)]
+struct Buffer {
+    filename: PathBuf,
+    lines: Vec<String>,
+    /// Whether the buffer has unsaved changes. If not, the modification time of the file on disk
+    /// is used when deciding whether local or remote changes are newer.
+    #[serde(default)]
+    modified: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CompleteTask {
+    #[serde(flatten)]
+    buffer: Buffer,
+    /// 0-based line of the todo to complete.
+    line: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TodoInfo {
+    line: usize,
+    state: &'static str,
+    content: Arc<str>,
+    id: Option<Arc<str>>,
+    due_at: Option<NaiveDate>,
+}
+
+impl From<&Todo> for TodoInfo {
+    fn from(todo: &Todo) -> Self {
+        Self {
+            line: todo.line,
+            state: todo.state.name(),
+            content: todo.content.clone(),
+            id: todo.id.clone(),
+            due_at: todo.due_at,
+        }
+    }
+}
+
+/// Result of an operation that might modify the buffer.
+#[derive(Debug, Clone, Serialize)]
+struct BufferUpdate {
+    /// Whether the buffer contents changed.
+    changed: bool,
+    /// New buffer contents (only if changed).
+    lines: Option<Vec<String>>,
+    todos: Vec<TodoInfo>,
+}
+
+impl BufferUpdate {
+    fn new(before: &[String], norg: &ParsedNorg) -> Self {
+        let mut lines: Vec<String> = norg
+            .lines()
+            .into_iter()
+            .map(|l| String::from_utf8_lossy(&l).into_owned())
+            .collect();
+        // buffer lines do not include the final newline
+        if norg.source_code().ends_with(b"\n") {
+            lines.pop();
+        }
+        let changed = lines != before;
+        Self {
+            changed,
+            lines: if changed { Some(lines) } else { None },
+            todos: norg.todos.iter().map(TodoInfo::from).collect(),
+        }
+    }
+}
+
+impl Buffer {
+    fn parse(&self) -> Result<ParsedNorg, Error> {
+        let modified_at: DateTime<Local> = if self.modified {
+            Local::now()
+        } else {
+            fs::metadata(&self.filename)
+                .and_then(|m| m.modified())
+                .map(DateTime::from)
+                .unwrap_or_else(|_| Local::now())
+        };
+        // like the file written from the buffer, which ends with a newline
+        let mut source = self.lines.join("\n");
+        source.push('\n');
+        ParsedNorg::from_source(&self.filename, source.into_bytes(), modified_at)
+    }
+}
+
+/// Serve msgpack-RPC requests on stdin/stdout until the editor closes the channel.
+pub async fn serve() -> Result<(), Error> {
+    let mut server = Server::default();
+
+    let mut stdin = BufReader::new(io::stdin());
+    let mut stdout = tokio::io::stdout();
+
+    loop {
+        // decoding reads blocking, which must not stall the runtime
+        let (message, reader) = tokio::task::spawn_blocking(move || {
+            let message = rmpv::decode::read_value(&mut stdin);
+            (message, stdin)
+        })
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        stdin = reader;
+
+        let message = match message {
+            Ok(message) => message,
+            Err(rmpv::decode::Error::InvalidMarkerRead(err))
+                if err.kind() == io::ErrorKind::UnexpectedEof =>
+            {
+                log::debug!("rpc channel closed");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let Some(message) = message.as_array() else {
+            log::warn!("ignoring invalid rpc message: {message}");
+            continue;
+        };
+
+        match (message.first().and_then(Value::as_u64), &message[..]) {
+            (Some(MSG_REQUEST), [_, msgid, method, params]) => {
+                let method = method.as_str().unwrap_or_default();
+                let (error, result) = match server.handle(method, params).await {
+                    Ok(result) => (Value::Nil, result),
+                    Err(err) => {
+                        log::debug!("error handling rpc request '{method}': {err}");
+                        (Value::from(err.to_string()), Value::Nil)
+                    }
+                };
+                let response =
+                    Value::Array(vec![MSG_RESPONSE.into(), msgid.clone(), error, result]);
+                let mut buf = Vec::new();
+                rmpv::encode::write_value(&mut buf, &response)?;
+                stdout.write_all(&buf).await?;
+                stdout.flush().await?;
+            }
+            (Some(MSG_NOTIFICATION), [_, method, params]) => {
+                let method = method.as_str().unwrap_or_default();
+                if let Err(err) = server.handle(method, params).await {
+                    log::warn!("error handling rpc notification '{method}': {err}");
+                }
+            }
+            _ => log::warn!("ignoring unsupported rpc message: {message:?}"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    auth: Option<Authenticator>,
+}
+
+impl Server {
+    // Only log in once the editor requests something that needs remote access.
+    async fn auth(&mut self) -> Result<Authenticator, Error> {
+        if self.auth.is_none() {
+            // stdout is the rpc channel and stdin must not be read: the login prompt goes to
+            // stderr and waits for the browser instead of a pasted redirect URL
+            self.auth = Some(
+                auth::login_with(LoginFlow {
+                    headless: false,
+                    port: None,
+                    account: false,
+                })
+                .await?,
+            );
+        }
+        Ok(self.auth.clone().unwrap())
+    }
+
+    async fn handle(&mut self, method: &str, params: &Value) -> Result<Value, Error> {
+        match method {
+            "list_todos" => {
+                let buffer: Buffer = single_param(params)?;
+                let norg = buffer.parse()?;
+                to_value(norg.todos.iter().map(TodoInfo::from).collect::<Vec<_>>())
+            }
+
+            "sync_buffer" => {
+                let buffer: Buffer = single_param(params)?;
+                let mut norg = buffer.parse()?;
+
+                let auth = self.auth().await?;
+                let tasks = get_tasks(auth.clone(), &CFG.tasklist).await?;
+                Syncer::for_buffer(CFG.tasklist.clone())
+                    .perform_norg(auth, &mut norg, &tasks[..])
+                    .await
+                    .during("syncing buffer")?;
+
+                to_value(BufferUpdate::new(&buffer.lines[..], &norg))
+            }
+
+            "complete_task" => {
+                let request: CompleteTask = single_param(params)?;
+                let mut norg = request.buffer.parse()?;
+
+                let idx = norg
+                    .todos
+                    .iter()
+                    .position(|t| t.line == request.line)
+                    .ok_or_else(|| Error::NotFound {
+                        what: format!("todo in line {}", request.line),
+                    })?;
+
+                if let Some(id) = norg.todos[idx].id.clone() {
+                    task_complete(self.auth().await?, &CFG.tasklist, &id).await?;
+                }
+                if norg.todos[idx].state != State::Done {
+                    norg.mark_completed(idx);
+                    norg.reparse(norg.source_code().to_vec())?;
+                }
+
+                to_value(BufferUpdate::new(&request.buffer.lines[..], &norg))
+            }
+
+            other => Err(Error::NotSupported {
+                arg: other.into(),
+                command: "nvim".into(),
+            }),
+        }
+    }
+}
+
+// Neovim sends the arguments of `rpcrequest` as array, we expect a single table/dict.
+fn single_param<T: for<'de> Deserialize<'de>>(params: &Value) -> Result<T, Error> {
+    let param = params
+        .as_array()
+        .and_then(|p| p.first())
+        .cloned()
+        .ok_or_else(|| Error::NotFound {
+            what: "rpc parameter".into(),
+        })?;
+    Ok(rmpv::ext::from_value(param)?)
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, Error> {
+    Ok(rmpv::ext::to_value(value)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::tasks::Task;
+    use pretty_assertions::assert_eq;
+
+    fn buffer(lines: &[&str]) -> Buffer {
+        Buffer {
+            filename: PathBuf::from("buffer.norg"),
+            lines: lines.iter().map(|l| l.to_string()).collect(),
+            modified: true,
+        }
+    }
+
+    fn task(id: &str, title: &str, completed: bool) -> Task {
+        Task {
+            completed,
+            id: id.into(),
+            title: title.into(),
+            modified_at: Local::now(),
+            due_at: None,
+            completed_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_buffer_pulls_completed() -> Result<(), Error> {
+        let buffer = buffer(&[
+            "* TODOs",
+            "  - ( ) Done remote %#taskid task1%",
+            "  - ( ) Open remote %#taskid task2%",
+        ]);
+        let mut norg = buffer.parse()?;
+        let tasks = [
+            task("task1", "Done remote", true),
+            task("task2", "Open remote", false),
+        ];
+
+        // nothing to push and titles match, so google is never asked
+        let (_, stats) = Syncer::for_buffer("tasklist".into())
+            .perform_norg(auth::offline_authenticator().await, &mut norg, &tasks[..])
+            .await?;
+        assert_eq!(stats.pull_completed.len(), 1);
+
+        let update = BufferUpdate::new(&buffer.lines[..], &norg);
+        assert!(update.changed);
+        assert_eq!(
+            update.lines.unwrap(),
+            [
+                "* TODOs",
+                "  - (x) Done remote %#taskid task1%",
+                "  - ( ) Open remote %#taskid task2%",
+            ]
+        );
+        assert_eq!(update.todos[0].state, "done");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unchanged_buffer() -> Result<(), Error> {
+        let buffer = buffer(&["* TODOs", "  - ( ) Open remote %#taskid task2%"]);
+        let mut norg = buffer.parse()?;
+
+        Syncer::for_buffer("tasklist".into())
+            .perform_norg(
+                auth::offline_authenticator().await,
+                &mut norg,
+                &[task("task2", "Open remote", false)],
+            )
+            .await?;
+
+        let update = BufferUpdate::new(&buffer.lines[..], &norg);
+        assert!(!update.changed);
+        assert_eq!(update.lines, None);
+        Ok(())
+    }
+}
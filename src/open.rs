@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::auth;
+use crate::cfg::CFG;
+use crate::editor::open_in_editor;
+use crate::error::Error;
+use crate::opts::Open as OpenOpts;
+use crate::search::{local_todos, Hit};
+use crate::select::select_plain_single;
+use crate::tasks::get_tasks;
+
+pub async fn perform_open(opts: &OpenOpts) -> Result<(), Error> {
+    let todos = local_todos(&opts.files_or_folders[..])?;
+    let candidates = match opts.query {
+        Some(ref query) => matching(todos, query, opts.offline).await?,
+        None => todos,
+    };
+
+    let todo = select_plain_single(candidates).ok_or_else(|| Error::NotFound {
+        what: match opts.query {
+            Some(ref query) => format!("local todo matching '{query}'"),
+            None => "local todos".into(),
+        },
+    })?;
+    open_in_editor(&todo.file, todo.line)
+}
+
+// Todos with the given task id or else those whose content contains the query
+// (case-insensitive). Only without local match, todos are found via the title of their remote task,
+// which is mapped to the todo via its task id comment.
+async fn matching(todos: Vec<Hit>, query: &str, offline: bool) -> Result<Vec<Hit>, Error> {
+    let local = local_matches(&todos, query);
+    if !local.is_empty() || offline {
+        return Ok(local);
+    }
+
+    let query_lower = query.to_lowercase();
+    let remote_ids: HashSet<Arc<str>> = get_tasks(auth::login().await?, &CFG.tasklist)
+        .await?
+        .into_iter()
+        .filter(|t| t.title.to_lowercase().contains(&query_lower))
+        .map(|t| t.id)
+        .collect();
+
+    Ok(todos
+        .into_iter()
+        .filter(|t| t.id.as_ref().is_some_and(|id| remote_ids.contains(id)))
+        .collect())
+}
+
+fn local_matches(todos: &[Hit], query: &str) -> Vec<Hit> {
+    if let Some(todo) = todos.iter().find(|t| t.id.as_deref() == Some(query)) {
+        return vec![todo.clone()];
+    }
+
+    let query_lower = query.to_lowercase();
+    todos
+        .iter()
+        .filter(|t| t.content.to_lowercase().contains(&query_lower))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::parse::State;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    fn hit(line: usize, content: &str, id: Option<&str>) -> Hit {
+        Hit {
+            file: PathBuf::from("todo.norg"),
+            line,
+            state: State::Undone,
+            content: content.into(),
+            id: id.map(Arc::from),
+            section: None,
+            date: None,
+            file_day: None,
+        }
+    }
+
+    #[test]
+    fn local_matches_by_id_or_content() {
+        let todos = [
+            hit(1, "Buy milk", Some("abc")),
+            hit(2, "Call about milk", None),
+            hit(3, "abc of cooking", None),
+        ];
+        let lines = |query: &str| {
+            local_matches(&todos, query)
+                .iter()
+                .map(|h| h.line)
+                .collect::<Vec<_>>()
+        };
+
+        // an exact task id wins over content
+        assert_eq!(lines("abc"), vec![1]);
+        assert_eq!(lines("MILK"), vec![1, 2]);
+        assert_eq!(lines("dentist"), Vec::<usize>::new());
+    }
+}
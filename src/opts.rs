@@ -3,10 +3,13 @@ use shadow_rs::shadow;
 shadow!(build);
 
 use camino::Utf8PathBuf;
+use chrono::{Datelike, Duration, Local, NaiveDate, Weekday};
 use clap::{
     crate_authors, crate_description, ArgAction, Args, ColorChoice, Parser, Subcommand, ValueEnum,
 };
 use clap_complete::Shell;
+
+use crate::cfg::RolloverLeave;
 use std::{path::PathBuf, str};
 
 pub static STDIN: Lazy<Utf8PathBuf> = Lazy::new(|| Utf8PathBuf::from("-"));
@@ -25,6 +28,10 @@ pub struct Opts {
     #[arg(short, long, action = ArgAction::Count)]
     pub verbose: u8,
 
+    /// Profile with separate config, client secret, tokens and sync state.
+    #[arg(long, global = true, env = "NEORG_TASK_SYNC_PROFILE")]
+    pub profile: Option<String>,
+
     #[command(subcommand)]
     pub command: Command,
 }
@@ -43,37 +50,256 @@ impl Opts {
     }
 }
 
+// Subcommands are inferred from prefixes, aliases keep abbreviations working that became ambiguous
+// with newer commands.
 #[derive(Subcommand, Debug)]
 pub enum Command {
+    /// List open todos grouped into overdue, today, this week and no date.
+    #[command(name = "agenda")]
+    Agenda(Agenda),
+
+    /// Move old completed todos into the archive section or file.
+    #[command(name = "archive")]
+    Archive(Archive),
+
     /// Auth related commands
-    #[command(name = "auth")]
+    #[command(name = "auth", alias = "a")]
     Auth(Auth),
 
+    /// Add a todo to today's journal file
+    #[command(name = "capture")]
+    Capture(Capture),
+
     /// Show config
-    #[command(name = "config")]
+    #[command(name = "config", alias = "c")]
     Config(Config),
 
+    /// Regenerate the dashboard listing all open todos.
+    #[command(name = "dashboard")]
+    Dashboard(Dashboard),
+
+    /// Check the whole setup and suggest fixes
+    #[command(name = "doctor")]
+    Doctor,
+
     /// Generate completions
     #[command(name = "generate")]
     Generate(Generate),
 
+    /// Interactively set up client secret, login, tasklist, journal and config file
+    #[command(name = "init")]
+    Init,
+
+    /// Check norg files for task id problems
+    #[command(name = "lint")]
+    Lint(Lint),
+
+    /// Serve msgpack-RPC requests from neovim on stdin/stdout
+    #[command(name = "nvim")]
+    Nvim,
+
+    /// Open a todo in $EDITOR, given by task id, title or interactive selection
+    #[command(name = "open")]
+    Open(Open),
+
     /// Run a parse action (mainly for debugging)
     #[command(name = "parse")]
     Parse(Parse),
 
+    /// Move unfinished todos from older journal files into the newest one.
+    #[command(name = "rollover")]
+    Rollover(Rollover),
+
+    /// Search todos in all norg files of the workspace
+    #[command(name = "search")]
+    Search(Search),
+
     /// Sync tasks between local file and google tasks.
+    #[command(alias = "s")]
     Sync(Sync),
 
-    /// Check which tasks are defined upstream (mainly for debugging)
-    #[command(name = "tasks")]
+    /// Manage remote tasklists
+    #[command(name = "tasklist")]
+    Tasklist(Tasklist),
+
+    /// List and manage remote tasks
+    #[command(name = "tasks", aliases = ["t", "ta", "tas", "task"])]
     Tasks(Tasks),
 }
 
+// The listing options are meaningless for the subcommands.
 #[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Tasks {
-    /// output as json
-    #[arg(short, long)]
+    /// output as json (same as --format json)
+    #[arg(short, long, conflicts_with = "format")]
     pub json: bool,
+
+    /// Output format of the task listing.
+    #[arg(short, long, value_enum, default_value_t = TasksFormat::Table)]
+    pub format: TasksFormat,
+
+    /// Only list open tasks.
+    #[arg(short, long, conflicts_with = "completed")]
+    pub open: bool,
+
+    /// Only list completed tasks.
+    #[arg(short, long)]
+    pub completed: bool,
+
+    /// Only list tasks due before the given date (YYYY-MM-DD).
+    #[arg(long)]
+    pub due_before: Option<NaiveDate>,
+
+    /// Only list tasks due after the given date (YYYY-MM-DD).
+    #[arg(long)]
+    pub due_after: Option<NaiveDate>,
+
+    /// Only list tasks updated on or after the given date (YYYY-MM-DD).
+    #[arg(long)]
+    pub updated_since: Option<NaiveDate>,
+
+    /// Only list tasks whose title matches the regex.
+    #[arg(short, long)]
+    pub title: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<TasksCommand>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum TasksFormat {
+    /// Aligned columns: status, due date, title, updated.
+    Table,
+
+    /// Single JSON array.
+    Json,
+
+    /// One JSON object per task.
+    Ndjson,
+
+    /// Comma-separated values with header.
+    Csv,
+}
+
+/// Tasks are given by id or by a query matching their title, which is resolved via a picker if
+/// it matches several tasks.
+#[derive(Subcommand, Debug)]
+pub enum TasksCommand {
+    /// Create a task.
+    #[command(name = "add")]
+    Add {
+        #[arg()]
+        title: String,
+
+        /// Due date (YYYY-MM-DD).
+        #[arg(short, long)]
+        due: Option<NaiveDate>,
+
+        #[arg(short, long)]
+        notes: Option<String>,
+    },
+
+    /// Delete a task.
+    #[command(name = "delete")]
+    Delete {
+        /// Id or title query.
+        #[arg()]
+        task: String,
+
+        /// Do not ask for confirmation.
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Mark an open task as completed.
+    #[command(name = "done")]
+    Done {
+        /// Id or title query.
+        #[arg()]
+        task: String,
+    },
+
+    /// Change title, due date or notes of a task.
+    #[command(name = "edit")]
+    Edit {
+        /// Id or title query.
+        #[arg()]
+        task: String,
+
+        #[arg(short, long)]
+        title: Option<String>,
+
+        /// Due date (YYYY-MM-DD).
+        #[arg(short, long, conflicts_with = "no_due")]
+        due: Option<NaiveDate>,
+
+        /// Remove the due date.
+        #[arg(long)]
+        no_due: bool,
+
+        #[arg(short, long)]
+        notes: Option<String>,
+    },
+
+    /// Move a task into another tasklist (it gets a new id there).
+    #[command(name = "move")]
+    Move {
+        /// Id or title query.
+        #[arg()]
+        task: String,
+
+        /// Id or title of the target tasklist.
+        #[arg()]
+        tasklist: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Tasklist {
+    #[command(subcommand)]
+    pub command: TasklistCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TasklistCommand {
+    /// Create a new tasklist.
+    #[command(name = "create")]
+    Create {
+        #[arg()]
+        title: String,
+    },
+
+    /// Delete a tasklist including all its tasks.
+    #[command(name = "delete")]
+    Delete {
+        /// Id or title of the tasklist.
+        #[arg()]
+        tasklist: String,
+
+        /// Do not ask for confirmation.
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// List all tasklists with their number of tasks, the configured one is marked.
+    #[command(name = "list")]
+    List {
+        /// output as json
+        #[arg(short, long)]
+        json: bool,
+    },
+
+    /// Rename a tasklist.
+    #[command(name = "rename")]
+    Rename {
+        /// Id or title of the tasklist.
+        #[arg()]
+        tasklist: String,
+
+        #[arg()]
+        title: String,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -82,10 +308,89 @@ pub struct Auth {
     pub command: AuthCommand,
 }
 
+impl Command {
+    /// Files or folders the command operates on.
+    pub fn paths(&self) -> &[PathBuf] {
+        match self {
+            Command::Agenda(opts) => &opts.files_or_folders[..],
+            Command::Archive(opts) => &opts.files_or_folders[..],
+            Command::Capture(opts) => opts.journal.as_slice(),
+            Command::Dashboard(opts) => &opts.files_or_folders[..],
+            Command::Lint(opts) => &opts.files_or_folders[..],
+            Command::Open(opts) => &opts.files_or_folders[..],
+            Command::Parse(opts) => std::slice::from_ref(&opts.target),
+            Command::Rollover(opts) => &opts.files_or_folders[..],
+            Command::Search(opts) => &opts.files_or_folders[..],
+            Command::Sync(opts) => &opts.files_or_folders[..],
+            Command::Auth(_)
+            | Command::Config(_)
+            | Command::Doctor
+            | Command::Generate(_)
+            | Command::Init
+            | Command::Nvim
+            | Command::Tasklist(_)
+            | Command::Tasks(_) => &[],
+        }
+    }
+
+    /// Whether the command reads the configured workspaces if no paths are given.
+    pub fn uses_workspaces(&self) -> bool {
+        match self {
+            Command::Capture(opts) => opts.journal.is_none(),
+            Command::Agenda(_)
+            | Command::Dashboard(_)
+            | Command::Lint(_)
+            | Command::Open(_)
+            | Command::Search(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the command creates a profile, which must not exist yet.
+    pub fn creates_profile(&self) -> bool {
+        matches!(
+            self,
+            Command::Config(Config {
+                command: ConfigCommand::Profile(Profile {
+                    command: ProfileCommand::Create { .. }
+                })
+            })
+        )
+    }
+}
+
 #[derive(Subcommand, Debug)]
 pub enum AuthCommand {
-    #[command(name = "login")]
-    Login,
+    #[command(name = "login", aliases = ["l", "lo", "log"])]
+    Login(Login),
+
+    /// Revoke the cached token and delete it.
+    #[command(name = "logout")]
+    Logout(Logout),
+
+    /// Show account, scopes and expiry of the cached token (does not log in).
+    #[command(name = "status")]
+    Status,
+}
+
+#[derive(Args, Debug)]
+pub struct Login {
+    /// Do not expect a browser on this machine: print the login URL and read the URL the browser
+    /// was redirected to from stdin.
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Fixed port for the local redirect server, e.g. to forward it via SSH (default: config value
+    /// `oauth_redirect_port`, random if not configured).
+    #[arg(short, long)]
+    pub port: Option<u16>,
+}
+
+#[derive(Args, Debug)]
+pub struct Logout {
+    /// Only delete the token cache, do not revoke the token remotely.
+    #[arg(long)]
+    pub local: bool,
 }
 
 #[derive(Args, Debug)]
@@ -96,14 +401,94 @@ pub struct Config {
 
 #[derive(Subcommand, Debug)]
 pub enum ConfigCommand {
+    /// Print the current value of a config key.
+    #[command(name = "get")]
+    Get(ConfigKey),
+
     #[command(name = "import")]
     Import(ImportConfig),
 
-    #[command(name = "show")]
-    Show,
+    /// Manage profiles
+    #[command(name = "profile")]
+    Profile(Profile),
+
+    /// Set a config key in config.yaml (value is parsed as YAML).
+    #[command(name = "set")]
+    Set(ConfigSet),
+
+    /// Show the effective config.
+    #[command(name = "show", alias = "s")]
+    Show(ShowConfig),
 
     #[command(name = "tasklist")]
     TaskList(TaskList),
+
+    /// Remove a config key from config.yaml, so that its default applies.
+    #[command(name = "unset")]
+    Unset(ConfigKey),
+}
+
+#[derive(Args, Debug)]
+pub struct ShowConfig {
+    /// Show where each value comes from (config file, env var, fallback file or default).
+    #[arg(short, long)]
+    pub sources: bool,
+
+    /// Output format.
+    #[arg(short, long, value_enum, default_value_t = ConfigFormat::Yaml)]
+    pub format: ConfigFormat,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigKey {
+    #[arg()]
+    pub key: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigSet {
+    #[arg()]
+    pub key: String,
+
+    #[arg()]
+    pub value: String,
+}
+
+#[derive(Args, Debug)]
+pub struct Profile {
+    #[command(subcommand)]
+    pub command: ProfileCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProfileCommand {
+    /// List all profiles, the active one is marked.
+    #[command(name = "list")]
+    List,
+
+    /// Create a new profile.
+    #[command(name = "create")]
+    Create {
+        #[arg()]
+        name: String,
+    },
+
+    /// Delete a profile including its secrets, tokens and sync state.
+    #[command(name = "delete")]
+    Delete {
+        #[arg()]
+        name: String,
+
+        /// Do not ask for confirmation.
+        #[arg(short, long)]
+        yes: bool,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -200,10 +585,20 @@ pub struct Sync {
     #[arg(long)]
     pub fix_missing: bool,
 
+    /// Move unfinished todos from older files into the file new remote tasks are pulled to
+    /// before syncing.
+    #[arg(long)]
+    pub rollover: bool,
+
     /// Pull new remote tasks to first file specified, instead.
     #[arg(short = 'f', long)]
     pub pull_to_first: bool,
 
+    /// Pull new remote tasks to the journal file of today, instead. It is created from the
+    /// configured template in the first folder specified if it does not exist.
+    #[arg(short = 't', long, conflicts_with = "pull_to_first")]
+    pub pull_to_today: bool,
+
     /// Do not sort filenames prior to syncing.
     #[arg(short = 's', long, alias = "wo-sor")]
     pub without_sort: bool,
@@ -223,6 +618,208 @@ pub struct Sync {
     /// Do not pull remote google tasks and insert them into the todo section.
     #[arg(short = 'l', long, alias = "wo-pull")]
     pub without_pull: bool,
+
+    /// Output format of the sync summary. Machine-readable reports are printed to stdout and
+    /// errors in single files are recorded instead of aborting the sync.
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    pub report: ReportFormat,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    /// Styled summary of changed files.
+    Text,
+
+    /// Single JSON object.
+    Json,
+
+    /// One JSON object per file followed by a summary object.
+    Ndjson,
+}
+
+/// Archive completed todos
+#[derive(Args, Debug)]
+pub struct Archive {
+    /// Files or folders to archive completed todos in.
+    #[arg(required = true)]
+    pub files_or_folders: Vec<PathBuf>,
+
+    /// Archive todos completed longer ago than this many days (default: config value
+    /// `archive_completed_todos_older_than_days`).
+    #[arg(short = 'd', long)]
+    pub older_than_days: Option<usize>,
+
+    /// Do not query completion times of remote tasks, only use when todos were first seen
+    /// completed locally.
+    #[arg(long)]
+    pub offline: bool,
+}
+
+/// Open todo dashboard
+#[derive(Args, Debug)]
+pub struct Dashboard {
+    /// Files or folders to collect open todos from (default: config value `workspaces`).
+    #[arg()]
+    pub files_or_folders: Vec<PathBuf>,
+
+    /// Where to write the dashboard (default: config value `dashboard_file`).
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Do not include remote tasks.
+    #[arg(long)]
+    pub offline: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct Agenda {
+    /// Files or folders to collect open todos from (default: config value `workspaces`).
+    #[arg()]
+    pub files_or_folders: Vec<PathBuf>,
+
+    /// Do not include remote-only tasks.
+    #[arg(long)]
+    pub offline: bool,
+}
+
+/// Add a todo to the journal file of today, which is created from the template if needed.
+#[derive(Args, Debug)]
+pub struct Capture {
+    /// Content of the todo.
+    #[arg()]
+    pub text: String,
+
+    /// Due date: YYYY-MM-DD, today, tomorrow or a weekday. Due dates are only stored remotely,
+    /// hence this requires --push.
+    #[arg(short, long, value_parser = parse_due, requires = "push")]
+    pub due: Option<NaiveDate>,
+
+    /// Section to add the todo to (default: config value `section_todos`).
+    #[arg(short, long)]
+    pub section: Option<String>,
+
+    /// Create the remote task right away and add its task id to the todo.
+    #[arg(short, long)]
+    pub push: bool,
+
+    /// Journal folder (default: first of config value `workspaces`).
+    #[arg(short, long)]
+    pub journal: Option<PathBuf>,
+}
+
+// Dates relative to today are resolved to the next such day (today included).
+fn parse_due(s: &str) -> Result<NaiveDate, String> {
+    let today = Local::now().date_naive();
+    match s.to_lowercase().as_str() {
+        "today" => return Ok(today),
+        "tomorrow" => return Ok(today + Duration::days(1)),
+        _ => {}
+    }
+    if let Ok(weekday) = s.parse::<Weekday>() {
+        let days_ahead =
+            (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+        return Ok(today + Duration::days(days_ahead as i64));
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| format!("expected YYYY-MM-DD, today, tomorrow or a weekday, got '{s}'"))
+}
+
+/// Open the location of a todo in $EDITOR.
+#[derive(Args, Debug)]
+pub struct Open {
+    /// Remote task id or part of the title, pick from all local todos if not given.
+    #[arg(short, long)]
+    pub query: Option<String>,
+
+    /// Files or folders to look for the todo in (default: config value `workspaces`).
+    #[arg()]
+    pub files_or_folders: Vec<PathBuf>,
+
+    /// Only match titles of local todos, not of remote tasks.
+    #[arg(long)]
+    pub offline: bool,
+}
+
+/// Search todos by content, state, task id, section or file date (case-insensitive regex).
+#[derive(Args, Debug)]
+pub struct Search {
+    /// Regex to search for.
+    #[arg()]
+    pub pattern: String,
+
+    /// Files or folders to search (default: config value `workspaces`).
+    #[arg()]
+    pub files_or_folders: Vec<PathBuf>,
+
+    /// Only show todos in the given state.
+    #[arg(short, long, value_enum)]
+    pub state: Option<StateFilter>,
+
+    /// Only show todos dated on or after the given date (due date or journal day).
+    #[arg(long)]
+    pub from: Option<NaiveDate>,
+
+    /// Only show todos dated on or before the given date (due date or journal day).
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+
+    /// Output format of the results.
+    #[arg(short, long, value_enum, default_value_t = SearchFormat::Text)]
+    pub format: SearchFormat,
+
+    /// Open the picked result in $EDITOR instead of printing its location (implies --format skim).
+    #[arg(short, long)]
+    pub edit: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum StateFilter {
+    /// Undone or pending.
+    Open,
+    Undone,
+    Pending,
+    Done,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum SearchFormat {
+    /// One line per todo with its location.
+    Text,
+
+    /// Single JSON array.
+    Json,
+
+    /// Pick a single todo interactively and print its location.
+    Skim,
+}
+
+/// Check norg files for task id problems
+#[derive(Args, Debug)]
+pub struct Lint {
+    /// Files or folders to check (default: config value `workspaces`).
+    #[arg()]
+    pub files_or_folders: Vec<PathBuf>,
+
+    /// Repair mangled task id comments whose id can be recovered.
+    #[arg(long)]
+    pub fix: bool,
+
+    /// Do not check whether the ids exist in the configured tasklist.
+    #[arg(long)]
+    pub offline: bool,
+}
+
+/// Carry over unfinished todos
+#[derive(Args, Debug)]
+pub struct Rollover {
+    /// Files or folders to roll over. Undone todos are moved into the todo section of the last
+    /// file specified (after sorting).
+    #[arg(required = true)]
+    pub files_or_folders: Vec<PathBuf>,
+
+    /// What to leave behind in the source file (default: config value `rollover_leave`).
+    #[arg(long, value_enum)]
+    pub leave: Option<RolloverLeave>,
 }
 
 #[derive(Debug, Clone, Args)]
@@ -235,6 +832,23 @@ pub struct CompletionOpts {
 mod tests {
     use super::*;
 
+    #[test]
+    fn due_relative_to_today() {
+        let today = Local::now().date_naive();
+        assert_eq!(parse_due("today"), Ok(today));
+        assert_eq!(parse_due("Tomorrow"), Ok(today + Duration::days(1)));
+        assert_eq!(
+            parse_due("2023-10-18"),
+            Ok(NaiveDate::from_ymd_opt(2023, 10, 18).unwrap())
+        );
+
+        let friday = parse_due("friday").unwrap();
+        assert_eq!(friday.weekday(), Weekday::Fri);
+        assert!(friday >= today && friday < today + Duration::days(7));
+
+        assert!(parse_due("someday").is_err());
+    }
+
     #[test]
     fn encode_stdin_as_pathbuf() {
         let path = Utf8PathBuf::from("-");
@@ -242,4 +856,61 @@ mod tests {
 
         assert_eq!(&path, Lazy::force(&STDIN));
     }
+
+    #[test]
+    fn open_takes_folders_positionally() {
+        let opts =
+            Opts::try_parse_from(["neorg-task-sync", "open", "notes", "-q", "milk"]).unwrap();
+        let Command::Open(open) = opts.command else {
+            panic!("expected open command");
+        };
+        assert_eq!(open.files_or_folders, vec![PathBuf::from("notes")]);
+        assert_eq!(open.query.as_deref(), Some("milk"));
+    }
+
+    #[test]
+    fn abbreviations_stay_unambiguous() {
+        let parse = |args: &[&str]| {
+            Opts::try_parse_from(["neorg-task-sync"].iter().chain(args))
+                .unwrap()
+                .command
+        };
+
+        assert!(matches!(parse(&["s", "notes"]), Command::Sync(_)));
+        assert!(matches!(parse(&["se", "milk"]), Command::Search(_)));
+        assert!(matches!(parse(&["c", "show"]), Command::Config(_)));
+        assert!(matches!(parse(&["ca", "milk"]), Command::Capture(_)));
+        assert!(matches!(parse(&["task"]), Command::Tasks(_)));
+        assert!(matches!(parse(&["taskl", "list"]), Command::Tasklist(_)));
+        assert!(matches!(
+            parse(&["a", "log"]),
+            Command::Auth(Auth {
+                command: AuthCommand::Login(_)
+            })
+        ));
+        assert!(matches!(
+            parse(&["con", "s"]),
+            Command::Config(Config {
+                command: ConfigCommand::Show(_)
+            })
+        ));
+    }
+
+    #[test]
+    fn tasks_listing_flags_conflict_with_subcommands() {
+        let parse = |args: &[&str]| Opts::try_parse_from(["neorg-task-sync"].iter().chain(args));
+
+        assert!(parse(&["tasks", "--json", "--open"]).is_ok());
+        assert!(parse(&["tasks", "add", "Buy milk", "--due", "2023-10-18"]).is_ok());
+        assert!(parse(&["tasks", "--json", "add", "Buy milk"]).is_err());
+    }
+
+    #[test]
+    fn tasks_json_conflicts_with_format() {
+        let parse = |args: &[&str]| Opts::try_parse_from(["neorg-task-sync"].iter().chain(args));
+
+        assert!(parse(&["tasks", "--json"]).is_ok());
+        assert!(parse(&["tasks", "--format", "csv"]).is_ok());
+        assert!(parse(&["tasks", "--json", "--format", "csv"]).is_err());
+    }
 }
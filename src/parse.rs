@@ -4,9 +4,11 @@ use chrono::NaiveDate;
 use chrono::NaiveDateTime;
 use chrono::NaiveTime;
 use once_cell::sync::Lazy;
+use regex::bytes::Regex;
 use std::collections::HashMap;
 use std::env;
 use std::fs;
+use std::ops::Range;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -15,7 +17,7 @@ use tree_sitter::Parser;
 use tree_sitter::Query;
 use tree_sitter::QueryCursor;
 
-use crate::cfg::CFG;
+use crate::cfg::{JournalLayout, CFG};
 use crate::error::WrapError;
 use crate::Error;
 
@@ -57,6 +59,8 @@ pub struct Todo {
     // positions that operate in a single line, points stay valid until the given line is modified
     pub in_line: TodoInLine,
     pub due_at: Option<NaiveDate>,
+    // todo without id, but with a comment that looks like a mangled task id
+    pub malformed_id: bool,
 }
 
 impl Todo {
@@ -71,14 +75,17 @@ impl Todo {
     }
 
     pub fn due_at_fmt(&self) -> Option<String> {
-        self.due_at.map(|d| {
-            NaiveDateTime::new(d, NaiveTime::default())
-                .and_utc()
-                .to_rfc3339()
-        })
+        self.due_at.map(due_rfc3339)
     }
 }
 
+/// Due date in the format expected by the API.
+pub fn due_rfc3339(due: NaiveDate) -> String {
+    NaiveDateTime::new(due, NaiveTime::default())
+        .and_utc()
+        .to_rfc3339()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct TodoBytes {
     pub content: ByteRange,
@@ -150,6 +157,14 @@ impl State {
             other => panic!("invalid kind: {other}"),
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Undone => "undone",
+            State::Pending => "pending",
+            State::Done => "done",
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -166,11 +181,45 @@ struct QueryIndices {
 pub struct ParsedNorg {
     source_code: Vec<u8>,
     pub todos: Vec<Todo>,
+    pub sections: Vec<Section>,
     pub line_number: LineNumbers,
     pub filename: PathBuf,
     pub modified_at: DateTime<Local>,
 }
 
+// Comments that were meant to be task ids but are not recognised as such, e.g. `%# taskid abc%`.
+// Captures: `#`, id, trailing words.
+static MANGLED_ID: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)%\s*(#)?\s*task[\s_-]*id\b\s*([A-Za-z0-9_-]*)([^%\n]*)%")
+        .expect("invalid regex")
+});
+
+// Without `#`, a comment only counts as task id if it contains a word as long as remote ids.
+const MIN_LEN_BARE_ID: usize = 16;
+
+fn find_mangled_id(text: &[u8]) -> Option<regex::bytes::Captures<'_>> {
+    MANGLED_ID
+        .captures_iter(text)
+        .find(|c| c.get(1).is_some() || c.get(2).is_some_and(|id| id.len() >= MIN_LEN_BARE_ID))
+}
+
+/// Task id comment of a todo that was not recognised as such.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MangledId {
+    pub line: usize,
+    /// Id that could be recovered unambiguously from the comment, i.e. without any other words.
+    pub id: Option<Arc<str>>,
+    /// Bytes of the comment within the line.
+    pub in_line: Range<usize>,
+}
+
+/// Top-level heading
+#[derive(Debug, PartialEq, Clone)]
+pub struct Section {
+    pub title: Arc<str>,
+    pub line: usize,
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct LineNumbers {
     pub todo_section: usize,
@@ -194,10 +243,14 @@ impl ParsedNorg {
             .collect()
     }
 
+    pub fn source_code(&self) -> &[u8] {
+        &self.source_code[..]
+    }
+
     pub fn backup(&self) -> Result<(), Error> {
         let full = fs::canonicalize(&self.filename)?;
         let full_name = full.to_string_lossy().replace('/', "%");
-        let copy_to = env::temp_dir().join(format!("neorg_task_sync_{full_name}"));
+        let copy_to = backup_dir().join(format!("neorg_task_sync_{full_name}"));
         fs::copy(&self.filename, copy_to)?;
         Ok(())
     }
@@ -207,6 +260,38 @@ impl ParsedNorg {
         Ok(())
     }
 
+    // Section the given line belongs to
+    pub fn section_of(&self, line: usize) -> Option<&Section> {
+        self.sections.iter().take_while(|s| s.line < line).last()
+    }
+
+    // First line after the section starting at the given line
+    pub fn section_end(&self, section_line: usize) -> usize {
+        self.sections
+            .iter()
+            .map(|s| s.line)
+            .find(|l| *l > section_line)
+            .unwrap_or(usize::MAX)
+    }
+
+    // Line ranges of the list items starting at the given lines, including their continuation
+    // lines and nested items. Items nested in another given item are part of its range.
+    pub fn item_spans(&self, item_lines: &[usize]) -> Vec<Range<usize>> {
+        let lines = self.lines();
+        let mut starts = item_lines.to_vec();
+        starts.sort_unstable();
+        starts.dedup();
+
+        let mut spans: Vec<Range<usize>> = Vec::new();
+        for start in starts {
+            if spans.last().is_some_and(|span| span.contains(&start)) {
+                continue;
+            }
+            spans.push(start..item_end(&lines[..], start));
+        }
+        spans
+    }
+
     pub fn idx_by_todo_id(&self, id: &str) -> usize {
         self.todos
             .iter()
@@ -221,6 +306,28 @@ impl ParsedNorg {
             .unwrap()
     }
 
+    /// Todos without id whose line contains a mangled task id comment.
+    pub fn mangled_ids(&self) -> Vec<MangledId> {
+        let lines = self.lines();
+        self.todos
+            .iter()
+            .filter(|t| t.malformed_id)
+            .filter_map(|t| {
+                let captures = find_mangled_id(&lines[t.line])?;
+                let trailing = captures.get(3).map_or(&b""[..], |m| m.as_bytes());
+                let id = captures
+                    .get(2)
+                    .filter(|id| !id.is_empty() && trailing.trim_ascii().is_empty())
+                    .map(|id| String::from_utf8_lossy(id.as_bytes()).into());
+                Some(MangledId {
+                    line: t.line,
+                    id,
+                    in_line: captures.get(0)?.range(),
+                })
+            })
+            .collect()
+    }
+
     pub fn open(file: &Path) -> Result<Self, Error> {
         let metadata = fs::metadata(file).during("reading metadata")?;
         let source_code = fs::read_to_string(file).during("reading norg file")?;
@@ -237,18 +344,25 @@ impl ParsedNorg {
         Ok(new)
     }
 
+    // Parse source code that does not (necessarily) correspond to the file on disk, e.g. the
+    // contents of an editor buffer.
+    pub fn from_source(
+        file: &Path,
+        source_code: Vec<u8>,
+        modified_at: DateTime<Local>,
+    ) -> Result<Self, Error> {
+        let mut new = ParsedNorg {
+            filename: file.into(),
+            modified_at,
+            ..Self::default()
+        };
+        new.reparse(source_code)?;
+        Ok(new)
+    }
+
     // Get day that this file governs, if it's parseable
-    fn parse_filename_day(&self) -> Result<NaiveDate, Error> {
-        Ok(NaiveDate::parse_from_str(
-            &self
-                .filename
-                .with_extension("")
-                .file_name()
-                .map(|s| s.to_string_lossy())
-                .unwrap_or_default(),
-            "%Y-%m-%d",
-        )
-        .during("parsing filename as date")?)
+    pub fn parse_filename_day(&self) -> Result<NaiveDate, Error> {
+        day_of_path(&self.filename, CFG.journal_layout)
     }
 
     pub fn reparse(&mut self, source_code: Vec<u8>) -> Result<(), Error> {
@@ -274,6 +388,7 @@ impl ParsedNorg {
         };
 
         let mut section_to_line: HashMap<Arc<str>, usize> = HashMap::new();
+        let mut sections = Vec::new();
 
         for (i, m) in cursor
             .matches(&query, tree.root_node(), &source_code[..])
@@ -342,6 +457,7 @@ impl ParsedNorg {
                                 bytes,
                                 in_line,
                                 due_at: None,
+                                malformed_id: false,
                             }
                         }
                         TODO_WITHOUT_TAG => {
@@ -349,6 +465,10 @@ impl ParsedNorg {
                                 continue;
                             }
                             let content = get_content(&node_content)?;
+                            let malformed_id = find_mangled_id(
+                                &source_code[bytes.content.start..bytes.content.end],
+                            )
+                            .is_some();
 
                             Todo {
                                 line,
@@ -358,6 +478,7 @@ impl ParsedNorg {
                                 bytes,
                                 in_line,
                                 due_at: None,
+                                malformed_id,
                             }
                         }
 
@@ -374,6 +495,10 @@ impl ParsedNorg {
                         .expect("no node for title");
                     let line = node_title.start_position().row;
                     let title = get_content(&node_title)?;
+                    sections.push(Section {
+                        title: title.clone(),
+                        line,
+                    });
                     section_to_line.insert(title, line);
                 }
 
@@ -387,6 +512,9 @@ impl ParsedNorg {
         self.todos = todos;
         self.source_code = source_code;
 
+        sections.sort_by_key(|s| s.line);
+        self.sections = sections;
+
         let header: Arc<str> = CFG.section_todos.clone();
 
         let line_todo_section = section_to_line.get(&header).cloned().unwrap_or(usize::MAX);
@@ -435,6 +563,59 @@ impl ParsedNorg {
         Ok(())
     }
 
+    // Insert new todos into the given section instead of the configured todo section.
+    pub fn set_todo_section(&mut self, title: &str) -> Result<(), Error> {
+        let line = self
+            .sections
+            .iter()
+            .find(|s| &*s.title == title)
+            .map(|s| s.line)
+            .ok_or_else(|| Error::NotFound {
+                what: format!("section: {title}"),
+            })?;
+        self.line_number = LineNumbers {
+            todo_section: line,
+            section_after_todo: self.section_end(line),
+        };
+        Ok(())
+    }
+
+    // Insert lines after the last todo in the todo section (or append them if there is none).
+    // Returns the line numbers of the inserted lines.
+    pub fn insert_todo_lines<I>(&mut self, new_lines: I) -> Result<Vec<usize>, Error>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+    {
+        let mut lines = self.lines();
+        // insert behind the whole last item, not into its continuation lines or child items
+        let first_line = match (
+            self.todos.is_empty(),
+            self.line_number.todo_section,
+            self.line_number.section_after_todo,
+        ) {
+            (false, usize::MAX, usize::MAX) => {
+                item_end(&lines[..], self.todos.last().unwrap().line)
+            }
+            (false, section_todo, section_next) => self
+                .todos
+                .iter()
+                .filter(|t| section_todo < t.line && t.line < section_next)
+                .next_back()
+                .map_or(section_todo + 1, |t| item_end(&lines[..], t.line)),
+            (true, usize::MAX, usize::MAX) => lines.len(),
+            (true, section_todo, _) => section_todo + 1,
+        };
+
+        let mut inserted = Vec::new();
+        for (i, new_line) in new_lines.into_iter().enumerate() {
+            lines.insert(first_line + i, new_line);
+            inserted.push(first_line + i);
+        }
+
+        self.set_lines(&lines[..])?;
+        Ok(inserted)
+    }
+
     pub fn mark_completed(&mut self, idx: usize) {
         let todo = &self.todos[idx];
         let len_state = todo.bytes.state.end - todo.bytes.state.start;
@@ -526,6 +707,119 @@ impl ParsedNorg {
     }
 }
 
+// Nesting level of a list item (`-`, `--`, `~~`, …), `None` for other lines.
+pub fn list_level(line: &[u8]) -> Option<usize> {
+    let trimmed = line.trim_ascii_start();
+    let marker = *trimmed.first().filter(|c| **c == b'-' || **c == b'~')?;
+    let level = trimmed.iter().take_while(|c| **c == marker).count();
+    (trimmed.get(level) == Some(&b' ')).then_some(level)
+}
+
+// Lower the list levels of an item and its nested items so that the item is at the top level.
+pub fn to_top_level(mut item: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    let shift = item
+        .first()
+        .and_then(|line| list_level(line))
+        .map_or(0, |level| level - 1);
+    if shift == 0 {
+        return item;
+    }
+    for line in item.iter_mut() {
+        if list_level(line).is_some() {
+            let len_indent = line.iter().take_while(|c| c.is_ascii_whitespace()).count();
+            line.drain(len_indent..len_indent + shift);
+        }
+    }
+    item
+}
+
+fn is_heading(line: &[u8]) -> bool {
+    let trimmed = line.trim_ascii_start();
+    let level = trimmed.iter().take_while(|c| **c == b'*').count();
+    level > 0 && trimmed.get(level) == Some(&b' ')
+}
+
+// First line after the list item starting at the given line. The item ends at a blank line, a
+// heading or the next item that is not nested deeper.
+fn item_end(lines: &[Vec<u8>], start: usize) -> usize {
+    let level = list_level(&lines[start]).unwrap_or(1);
+    lines
+        .iter()
+        .enumerate()
+        .skip(start + 1)
+        .find(|(_, line)| {
+            line.trim_ascii().is_empty()
+                || is_heading(line)
+                || list_level(line).is_some_and(|l| l <= level)
+        })
+        .map_or(lines.len(), |(i, _)| i)
+}
+
+/// Day of a journal file, given by its name (flat layout) or its path (nested layout).
+pub fn day_of_path(path: &Path, layout: JournalLayout) -> Result<NaiveDate, Error> {
+    let stem = path.with_extension("");
+    let name = |p: Option<&Path>| {
+        p.and_then(|p| p.file_name())
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+
+    match layout {
+        JournalLayout::Flat => Ok(NaiveDate::parse_from_str(&name(Some(&stem)), "%Y-%m-%d")
+            .during("parsing filename as date")?),
+        JournalLayout::Nested => {
+            // <year>/<month>/<day>.norg
+            let month = stem.parent();
+            let year = month.and_then(|m| m.parent());
+            Ok(NaiveDate::parse_from_str(
+                &format!("{}-{}-{}", name(year), name(month), name(Some(&stem))),
+                "%Y-%m-%d",
+            )
+            .during("parsing path as date")?)
+        }
+    }
+}
+
+// Norg link from one file to another file (and optionally line), paths are given without the
+// `.norg` extension and relative if both files share the same folder.
+pub fn norg_link(from: &Path, to: &Path, line: Option<usize>) -> String {
+    let to_no_ext = to.with_extension("");
+    let target = if from.parent() == to.parent() {
+        to_no_ext
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    } else {
+        fs::canonicalize(to)
+            .map(|p| p.with_extension(""))
+            .unwrap_or(to_no_ext)
+            .to_string_lossy()
+            .into_owned()
+    };
+    match line {
+        // norg line numbers are 1-based
+        Some(line) => format!("{{:{target}:{}}}", line + 1),
+        None => format!("{{:{target}:}}"),
+    }
+}
+
+// Link text must not end the link early.
+pub fn escape_link_text(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+/// Folder backups of modified files are written to.
+pub fn backup_dir() -> PathBuf {
+    env::temp_dir()
+}
+
+/// Check that the norg grammar loads and the todo query compiles against it.
+pub fn check_query() -> Result<(), Error> {
+    Parser::new().set_language(tree_sitter_norg::language())?;
+    Query::new(tree_sitter_norg::language(), &QUERY_TODO)?;
+    Ok(())
+}
+
 fn get_query() -> Result<(Arc<Query>, QueryIndices), Error> {
     static QUERY: Lazy<Arc<Query>> = Lazy::new(|| {
         Arc::new(
@@ -588,4 +882,140 @@ mod test {
 
         Ok(())
     }
+
+    static MANGLED_NORG: &str = r###"* TODOs
+  - ( ) Fine %#taskid foobar1%
+  - ( ) Space after hash %# taskid foobar2%
+  - ( ) Without id %# taskid%
+  - ( ) No comment at all
+  - ( ) Check 50% task id coverage
+  - ( ) Without hash %task id MTIzNDU2Nzg5MDEyMzQ1Njc4OTA%
+  - ( ) Short word without hash %task id abc%
+"###;
+
+    static SECTIONS_NORG: &str = r###"* TODOs
+  - ( ) First %#taskid foobar1%
+
+* Errands
+  - ( ) Buy milk
+
+* Notes
+"###;
+
+    #[test]
+    fn insert_into_section() -> Result<(), Error> {
+        let mut norg = ParsedNorg::from_source(
+            Path::new("sections.norg"),
+            SECTIONS_NORG.as_bytes().to_vec(),
+            Local::now(),
+        )?;
+        norg.set_todo_section("Errands")?;
+        let inserted = norg.insert_todo_lines([b"- ( ) Call bob".to_vec()])?;
+
+        assert_eq!(inserted, vec![5]);
+        assert_eq!(norg.todos.len(), 3);
+        assert_eq!(&*norg.todos[2].content, "Call bob");
+        assert_eq!(norg.todos[2].line, 5);
+        assert!(norg.set_todo_section("Missing").is_err());
+
+        Ok(())
+    }
+
+    static NESTED_NORG: &str = r###"* TODOs
+  - (x) Parent
+    continued on the next line
+  -- ( ) Child
+  --- (x) Grandchild
+  - ( ) Sibling
+  - (x) Last
+    ~~ Ordered child
+
+  Paragraph after the list
+* Notes
+"###;
+
+    #[test]
+    fn item_spans() -> Result<(), Error> {
+        let norg = ParsedNorg::from_source(
+            Path::new("nested.norg"),
+            NESTED_NORG.as_bytes().to_vec(),
+            Local::now(),
+        )?;
+        // the grandchild is part of its parent
+        assert_eq!(norg.item_spans(&[4, 1, 6]), vec![1..5, 6..8]);
+        assert_eq!(norg.item_spans(&[3]), vec![3..5]);
+        assert_eq!(norg.item_spans(&[5]), vec![5..6]);
+        Ok(())
+    }
+
+    #[test]
+    fn mangled_id_regex() {
+        for (text, id) in [
+            ("%# taskid abc%", Some("abc")),
+            ("%#task-id abc%", Some("abc")),
+            ("%#TaskID%", Some("")),
+            (
+                "% task_id MTIzNDU2Nzg5MDEyMzQ1Njc4OTA %",
+                Some("MTIzNDU2Nzg5MDEyMzQ1Njc4OTA"),
+            ),
+            // prose
+            ("Check 50% task id coverage", None),
+            ("50% task id coverage, 20% done", None),
+            // unclosed
+            ("%# taskid abc", None),
+            // without `#` and without recognisable id
+            ("%task id abc%", None),
+        ] {
+            let got = find_mangled_id(text.as_bytes())
+                .map(|c| String::from_utf8_lossy(c.get(2).unwrap().as_bytes()).into_owned());
+            assert_eq!(got.as_deref(), id, "{text}");
+        }
+    }
+
+    #[test]
+    fn mangled_ids() -> Result<(), Error> {
+        let norg = ParsedNorg::from_source(
+            Path::new("mangled.norg"),
+            MANGLED_NORG.as_bytes().to_vec(),
+            Local::now(),
+        )?;
+        let mangled = norg.mangled_ids();
+
+        assert_eq!(mangled.len(), 3);
+        assert_eq!(mangled[0].line, 2);
+        assert_eq!(mangled[0].id.as_deref(), Some("foobar2"));
+        assert_eq!(mangled[1].line, 3);
+        assert_eq!(mangled[1].id, None);
+        assert_eq!(mangled[2].line, 6);
+        assert_eq!(
+            mangled[2].id.as_deref(),
+            Some("MTIzNDU2Nzg5MDEyMzQ1Njc4OTA")
+        );
+
+        let malformed: Vec<usize> = norg
+            .todos
+            .iter()
+            .filter(|t| t.malformed_id)
+            .map(|t| t.line)
+            .collect();
+        assert_eq!(malformed, vec![2, 3, 6]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn day_of_nested_path() {
+        let day = NaiveDate::from_ymd_opt(2023, 10, 18);
+        for (path, layout, want) in [
+            ("notes/2023-10-18.norg", JournalLayout::Flat, day),
+            ("notes/2023/10/18.norg", JournalLayout::Nested, day),
+            ("2023/10/18.norg", JournalLayout::Nested, day),
+            ("notes/2023/10/18.norg", JournalLayout::Flat, None),
+            ("notes/2023-10-18.norg", JournalLayout::Nested, None),
+            ("notes/10/18.norg", JournalLayout::Nested, None),
+            ("notes/index.norg", JournalLayout::Nested, None),
+        ] {
+            assert_eq!(day_of_path(Path::new(path), layout).ok(), want, "{path}");
+        }
+    }
 }
@@ -0,0 +1,303 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::opts::ReportFormat;
+use crate::sync::{SyncStats, TaskRef};
+use crate::tasks::Task;
+
+/// Version of the machine-readable report, bump on incompatible changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Machine-readable report of a full sync.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncReport {
+    pub schema_version: u32,
+    pub tasklist: Arc<str>,
+    pub started_at: DateTime<Local>,
+    pub duration_ms: u64,
+    pub files: Vec<FileReport>,
+    pub cleared: Cleared,
+    /// Errors that did not belong to a single file.
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub file: PathBuf,
+    pub duration_ms: u64,
+    pub counts: Counts,
+    pub tasks: Tasks,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Counts {
+    pub pull_completed: usize,
+    pub push_completed: usize,
+    pub pull_new: usize,
+    pub push_new: usize,
+    pub newer_local: usize,
+    pub newer_remote: usize,
+    pub malformed_id: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Tasks {
+    pub pull_completed: Vec<TaskRef>,
+    pub push_completed: Vec<TaskRef>,
+    pub pull_new: Vec<TaskRef>,
+    pub push_new: Vec<TaskRef>,
+    pub newer_local: Vec<TaskRef>,
+    pub newer_remote: Vec<TaskRef>,
+    pub malformed_id: Vec<TaskRef>,
+}
+
+/// Completed tasks removed from remote because of `clear_completed_tasks_older_than_days`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Cleared {
+    pub older_than_days: Option<usize>,
+    pub count: usize,
+    pub tasks: Vec<TaskRef>,
+}
+
+impl Cleared {
+    pub fn new(older_than_days: Option<usize>, tasks: &[Task]) -> Self {
+        Self {
+            older_than_days,
+            count: tasks.len(),
+            tasks: tasks.iter().map(TaskRef::from).collect(),
+        }
+    }
+}
+
+impl FileReport {
+    pub fn new(stats: SyncStats, duration: Duration) -> Self {
+        Self {
+            file: stats.file,
+            duration_ms: duration.as_millis() as u64,
+            counts: Counts {
+                pull_completed: stats.pull_completed.len(),
+                push_completed: stats.push_completed.len(),
+                pull_new: stats.pull_new.len(),
+                push_new: stats.push_new.len(),
+                newer_local: stats.newer_local.len(),
+                newer_remote: stats.newer_remote.len(),
+                malformed_id: stats.malformed_id.len(),
+            },
+            tasks: Tasks {
+                pull_completed: stats.pull_completed,
+                push_completed: stats.push_completed,
+                pull_new: stats.pull_new,
+                push_new: stats.push_new,
+                newer_local: stats.newer_local,
+                newer_remote: stats.newer_remote,
+                malformed_id: stats.malformed_id,
+            },
+            error: None,
+        }
+    }
+
+    pub fn failed(file: PathBuf, error: &Error, duration: Duration) -> Self {
+        Self {
+            file,
+            duration_ms: duration.as_millis() as u64,
+            counts: Counts::default(),
+            tasks: Tasks::default(),
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+impl From<&FileReport> for SyncStats {
+    fn from(report: &FileReport) -> Self {
+        let tasks = report.tasks.clone();
+        Self {
+            file: report.file.clone(),
+            pull_completed: tasks.pull_completed,
+            push_completed: tasks.push_completed,
+            pull_new: tasks.pull_new,
+            push_new: tasks.push_new,
+            newer_local: tasks.newer_local,
+            newer_remote: tasks.newer_remote,
+            malformed_id: tasks.malformed_id,
+        }
+    }
+}
+
+// Records for newline-delimited output: one per file followed by a summary.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record<'a> {
+    File(&'a FileReport),
+    Summary {
+        schema_version: u32,
+        tasklist: &'a str,
+        started_at: &'a DateTime<Local>,
+        duration_ms: u64,
+        num_files: usize,
+        cleared: &'a Cleared,
+        errors: &'a [String],
+    },
+}
+
+impl SyncReport {
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty() || self.files.iter().any(|f| f.error.is_some())
+    }
+
+    pub fn write(&self, format: ReportFormat) -> Result<(), Error> {
+        let mut stdout = io::stdout().lock();
+        match format {
+            ReportFormat::Text => {}
+            ReportFormat::Json => {
+                serde_json::to_writer_pretty(&mut stdout, self)?;
+                writeln!(stdout)?;
+            }
+            ReportFormat::Ndjson => {
+                for file in self.files.iter() {
+                    serde_json::to_writer(&mut stdout, &Record::File(file))?;
+                    writeln!(stdout)?;
+                }
+                let summary = Record::Summary {
+                    schema_version: self.schema_version,
+                    tasklist: &self.tasklist,
+                    started_at: &self.started_at,
+                    duration_ms: self.duration_ms,
+                    num_files: self.files.len(),
+                    cleared: &self.cleared,
+                    errors: &self.errors[..],
+                };
+                serde_json::to_writer(&mut stdout, &summary)?;
+                writeln!(stdout)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .expect("object")
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    // The report is meant to be parsed by scripts, changing it needs a new schema version.
+    #[test]
+    fn stable_schema() -> Result<(), Error> {
+        assert_eq!(SCHEMA_VERSION, 1);
+
+        let done = TaskRef {
+            id: Some("task1".into()),
+            title: "Buy milk".into(),
+            line: Some(3),
+        };
+        let stats = SyncStats {
+            file: PathBuf::from("2023-10-18.norg"),
+            pull_completed: vec![done],
+            push_completed: Vec::new(),
+            pull_new: Vec::new(),
+            push_new: Vec::new(),
+            newer_local: Vec::new(),
+            newer_remote: Vec::new(),
+            malformed_id: Vec::new(),
+        };
+        let failed = Error::NotFound {
+            what: "remote task".into(),
+        };
+        let report = SyncReport {
+            schema_version: SCHEMA_VERSION,
+            tasklist: "tasklist1".into(),
+            started_at: Local::now(),
+            duration_ms: 12,
+            files: vec![
+                FileReport::new(stats, Duration::from_millis(5)),
+                FileReport::failed(
+                    PathBuf::from("2023-10-19.norg"),
+                    &failed,
+                    Duration::from_millis(1),
+                ),
+            ],
+            cleared: Cleared::new(Some(30), &[]),
+            errors: vec!["writing dashboard: denied".into()],
+        };
+        let value = serde_json::to_value(&report)?;
+
+        assert_eq!(
+            keys(&value),
+            [
+                "cleared",
+                "duration_ms",
+                "errors",
+                "files",
+                "schema_version",
+                "started_at",
+                "tasklist"
+            ]
+        );
+        assert_eq!(value["schema_version"], json!(1));
+        assert_eq!(value["tasklist"], json!("tasklist1"));
+        assert_eq!(
+            value["cleared"],
+            json!({"older_than_days": 30, "count": 0, "tasks": []})
+        );
+        assert_eq!(value["errors"], json!(["writing dashboard: denied"]));
+
+        let file = &value["files"][0];
+        assert_eq!(
+            keys(file),
+            ["counts", "duration_ms", "error", "file", "tasks"]
+        );
+        assert_eq!(file["file"], json!("2023-10-18.norg"));
+        assert_eq!(file["error"], Value::Null);
+        assert_eq!(
+            file["counts"],
+            json!({
+                "pull_completed": 1,
+                "push_completed": 0,
+                "pull_new": 0,
+                "push_new": 0,
+                "newer_local": 0,
+                "newer_remote": 0,
+                "malformed_id": 0,
+            })
+        );
+        assert_eq!(
+            keys(&file["tasks"]),
+            [
+                "malformed_id",
+                "newer_local",
+                "newer_remote",
+                "pull_completed",
+                "pull_new",
+                "push_completed",
+                "push_new"
+            ]
+        );
+        assert_eq!(
+            file["tasks"]["pull_completed"],
+            json!([{"id": "task1", "title": "Buy milk", "line": 3}])
+        );
+
+        let failed = &value["files"][1];
+        assert_eq!(failed["error"], json!("not found: remote task"));
+        assert_eq!(failed["counts"]["pull_completed"], json!(0));
+        Ok(())
+    }
+}
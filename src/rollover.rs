@@ -0,0 +1,282 @@
+use console::style;
+use std::path::{Path, PathBuf};
+
+use crate::cfg::{RolloverLeave, CFG};
+use crate::error::Error;
+use crate::opts::Rollover as RolloverOpts;
+use crate::parse::{day_of_path, escape_link_text, norg_link, to_top_level, ParsedNorg, State};
+use crate::sync::get_files_from_folders;
+
+#[derive(Debug, Clone)]
+pub struct RolloverStats {
+    pub file: PathBuf,
+    pub target: PathBuf,
+    pub num_moved: usize,
+}
+
+impl std::fmt::Display for RolloverStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{file}: {arrow} {num} → {target}",
+            file = self.file.display(),
+            arrow = style("⇥").bold().cyan(),
+            num = style(self.num_moved).cyan(),
+            target = self.target.display(),
+        )
+    }
+}
+
+pub fn perform_rollover(opts: &RolloverOpts) -> Result<(), Error> {
+    let files = get_files_from_folders(&opts.files_or_folders[..], &CFG.ignore_filenames[..])?;
+
+    // the newest journal file
+    let target = files
+        .iter()
+        .filter_map(|f| Some((day_of_path(f, CFG.journal_layout).ok()?, f)))
+        .max()
+        .map(|(_, f)| f.clone())
+        .ok_or_else(|| Error::NotFound {
+            what: "journal files to roll over".into(),
+        })?;
+
+    for stats in rollover(
+        &files[..],
+        &target,
+        opts.leave.unwrap_or(CFG.rollover_leave),
+    )? {
+        println!("{stats}");
+    }
+    Ok(())
+}
+
+// Move undone todos from the todo section of older journal files into the todo section of the
+// target journal file, leaving a link or marker behind. Task ids are moved along so that remote
+// tasks stay linked. Files that are no journal files are left alone.
+pub fn rollover(
+    files: &[PathBuf],
+    target: &Path,
+    leave: RolloverLeave,
+) -> Result<Vec<RolloverStats>, Error> {
+    let target_day = day_of_path(target, CFG.journal_layout)?;
+
+    let mut moved = Vec::new();
+    let mut sources = Vec::new();
+    let mut stats = Vec::new();
+
+    let older = files
+        .iter()
+        .filter(|f| day_of_path(f, CFG.journal_layout).is_ok_and(|day| day < target_day));
+    for file in older {
+        let mut norg = ParsedNorg::open(file)?;
+        let (num_moved, lines) = take_undone(&mut norg, target, leave)?;
+        if num_moved == 0 {
+            continue;
+        }
+
+        moved.extend(lines);
+        sources.push(norg);
+        stats.push(RolloverStats {
+            file: file.clone(),
+            target: target.to_path_buf(),
+            num_moved,
+        });
+    }
+
+    if moved.is_empty() {
+        return Ok(stats);
+    }
+
+    // Write target first so that todos are rather duplicated than lost on error.
+    let mut norg_target = ParsedNorg::open(target)?;
+    norg_target.insert_todo_lines(moved)?;
+    norg_target.backup()?;
+    norg_target.write()?;
+
+    for norg in sources {
+        norg.backup()?;
+        norg.write()?;
+    }
+
+    Ok(stats)
+}
+
+// Replace the undone items of the todo section, including their continuation lines and nested
+// items, by a link or marker. Returns the number of items and their lines with indentation kept.
+// Items nested below a done item are lowered to the top list level, as they lose their parent.
+fn take_undone(
+    norg: &mut ParsedNorg,
+    target: &Path,
+    leave: RolloverLeave,
+) -> Result<(usize, Vec<Vec<u8>>), Error> {
+    let section = norg.line_number.todo_section..norg.line_number.section_after_todo;
+    let undone: Vec<usize> = norg
+        .todos
+        .iter()
+        .filter(|t| t.state == State::Undone && section.contains(&t.line))
+        .map(|t| t.line)
+        .collect();
+    let spans = norg.item_spans(&undone);
+    if spans.is_empty() {
+        return Ok((0, Vec::new()));
+    }
+
+    let mut lines = norg.lines();
+    let mut moved = Vec::new();
+    for span in spans.iter().rev() {
+        // spans start at undone todos
+        let todo = norg.todos.iter().find(|t| t.line == span.start).unwrap();
+        let line = &lines[span.start];
+        let len_marker = line.iter().position(|c| *c == b'(').unwrap_or(line.len());
+        let marker = String::from_utf8_lossy(&line[..len_marker]).into_owned();
+
+        let content = todo.content.clone();
+        let left = match leave {
+            RolloverLeave::Link => format!(
+                "{marker}Rolled over: {link}[{content}]",
+                link = norg_link(&norg.filename, target, None),
+                content = escape_link_text(&content),
+            ),
+            RolloverLeave::Marker => format!("{marker}(_) {content}"),
+        }
+        .into_bytes();
+
+        let item: Vec<Vec<u8>> = lines.splice(span.clone(), [left]).collect();
+        moved.splice(0..0, to_top_level(item));
+    }
+
+    norg.set_lines(&lines[..])?;
+    Ok((spans.len(), moved))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::Local;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    static OLDER_GIVEN: &str = r###"* TODOs
+  - ( ) Still open %#taskid foobar1%
+  -- ( ) Child step
+  - (x) Already done %#taskid foobar2%
+  - ( ) Read ] docs
+
+* Notes
+  - ( ) Not in the todo section
+"###;
+
+    static OLDER_WANT: &str = r###"* TODOs
+  - Rolled over: {:2023-10-18:}[Still open]
+  - (x) Already done %#taskid foobar2%
+  - Rolled over: {:2023-10-18:}[Read \] docs]
+
+* Notes
+  - ( ) Not in the todo section
+"###;
+
+    static NEWER_GIVEN: &str = r###"* TODOs
+  - ( ) Todo of today %#taskid foobar3%
+    continued on the next line
+
+* Notes
+"###;
+
+    static NEWER_WANT: &str = r###"* TODOs
+  - ( ) Todo of today %#taskid foobar3%
+    continued on the next line
+  - ( ) Still open %#taskid foobar1%
+  -- ( ) Child step
+  - ( ) Read ] docs
+
+* Notes
+"###;
+
+    // neither older journal files nor the target
+    static UNTOUCHED: &str = r###"* TODOs
+  - ( ) Stays here
+"###;
+
+    #[test]
+    fn rollover_link() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let older = dir.path().join("2023-10-17.norg");
+        let newer = dir.path().join("2023-10-18.norg");
+        let future = dir.path().join("2023-10-19.norg");
+        let project = dir.path().join("project.norg");
+        fs::write(&older, OLDER_GIVEN)?;
+        fs::write(&newer, NEWER_GIVEN)?;
+        fs::write(&future, UNTOUCHED)?;
+        fs::write(&project, UNTOUCHED)?;
+
+        let files = [
+            older.clone(),
+            newer.clone(),
+            future.clone(),
+            project.clone(),
+        ];
+        let stats = rollover(&files, &newer, RolloverLeave::Link)?;
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].num_moved, 2);
+
+        assert_eq!(fs::read_to_string(older)?, OLDER_WANT);
+        assert_eq!(fs::read_to_string(newer)?, NEWER_WANT);
+        assert_eq!(fs::read_to_string(future)?, UNTOUCHED);
+        assert_eq!(fs::read_to_string(project)?, UNTOUCHED);
+
+        // the target has to be a journal file
+        assert!(rollover(&files, &project, RolloverLeave::Link).is_err());
+
+        Ok(())
+    }
+
+    static NESTED_GIVEN: &str = r###"* TODOs
+  - (x) Done parent
+  -- ( ) Open child
+     continued
+  --- ( ) Open grandchild
+  - ( ) Open
+  - ( ) Open with done child
+  -- (x) Done child
+"###;
+
+    static NESTED_WANT: &str = r###"* TODOs
+  - (x) Done parent
+  -- (_) Open child
+  - (_) Open
+  - (_) Open with done child
+"###;
+
+    #[test]
+    fn take_nested_items() -> Result<(), Error> {
+        let mut norg = ParsedNorg::from_source(
+            Path::new("2023-10-17.norg"),
+            NESTED_GIVEN.as_bytes().to_vec(),
+            Local::now(),
+        )?;
+        let (num_moved, moved) = take_undone(
+            &mut norg,
+            Path::new("2023-10-18.norg"),
+            RolloverLeave::Marker,
+        )?;
+
+        assert_eq!(num_moved, 3);
+        assert_eq!(
+            moved,
+            [
+                // lowered, as the done parent stays
+                "  - ( ) Open child",
+                "     continued",
+                "  -- ( ) Open grandchild",
+                "  - ( ) Open",
+                "  - ( ) Open with done child",
+                "  -- (x) Done child",
+            ]
+            .map(|l| l.as_bytes().to_vec())
+        );
+        assert_eq!(String::from_utf8_lossy(norg.source_code()), NESTED_WANT);
+
+        Ok(())
+    }
+}
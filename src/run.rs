@@ -1,46 +1,122 @@
 use clap::CommandFactory;
 use clap_complete::generate;
 use console::style;
-use std::io::{self, Write};
 use std::sync::Arc;
 
+use crate::agenda::perform_agenda;
+use crate::archive::perform_archive;
 use crate::auth;
-use crate::auth::login;
+use crate::auth::{login_with, logout, status, LoginFlow};
+use crate::capture::perform_capture;
 use crate::cfg;
-use crate::cfg::CFG;
+use crate::cfg::{Config, CFG};
+use crate::dashboard::perform_dashboard;
+use crate::doctor::perform_doctor;
 use crate::error::Error;
 use crate::error::WrapError;
+use crate::init::perform_init;
+use crate::lint::perform_lint;
+use crate::nvim;
+use crate::open::perform_open;
 use crate::opts::AuthCommand;
 use crate::opts::Command;
 use crate::opts::ConfigCommand;
 use crate::opts::ConfigOperation;
 use crate::opts::GenerateTarget;
 use crate::opts::Opts;
+use crate::opts::ProfileCommand;
 use crate::parse::ParsedNorg;
-use crate::select::select_plain_single;
+use crate::rollover::perform_rollover;
+use crate::search::perform_search;
+use crate::select::{confirm, select_plain_single};
 use crate::sync::perform_sync;
+use crate::task_commands::{perform_tasks_command, perform_tasks_list};
+use crate::tasklist::perform_tasklist;
 use crate::tasks::get_tasklists;
-use crate::tasks::get_tasks;
 use crate::tasks::print_tasklists;
 use crate::tasks::TaskList;
 
 pub async fn run(opts: &Opts) -> Result<(), Error> {
+    cfg::set_profile(opts.profile.as_deref(), opts.command.creates_profile())?;
+    let workspaces;
+    let paths = match opts.command.paths() {
+        [] if opts.command.uses_workspaces() => {
+            workspaces = Config::workspaces()?;
+            &workspaces[..]
+        }
+        paths => paths,
+    };
+    // doctor reports a broken local config instead of failing
+    let local_config = match cfg::discover_local_config(paths) {
+        Err(err) if !matches!(opts.command, Command::Doctor) => return Err(err),
+        result => result,
+    };
+
     match opts.command {
+        Command::Agenda(ref opts) => perform_agenda(opts).await?,
+
+        Command::Archive(ref opts) => perform_archive(opts).await?,
+
         Command::Auth(ref auth) => {
             match auth.command {
-                AuthCommand::Login => login().await.during("logging in")?,
+                AuthCommand::Login(ref opts) => {
+                    login_with(LoginFlow {
+                        headless: opts.headless,
+                        port: opts.port,
+                        account: true,
+                    })
+                    .await
+                    .during("logging in")?;
+                }
+                AuthCommand::Logout(ref opts) => logout(opts.local).await.during("logging out")?,
+                AuthCommand::Status => status().await.during("showing auth status")?,
             };
         }
 
+        Command::Capture(ref opts) => perform_capture(opts).await?,
+
         Command::Config(ref cfg) => {
             match &cfg.command {
+                ConfigCommand::Get(ref opts) => match cfg::get_key(&opts.key)? {
+                    serde_json::Value::Null => {}
+                    serde_json::Value::String(value) => println!("{value}"),
+                    value => println!("{value}"),
+                },
+
+                ConfigCommand::Set(ref opts) => cfg::set_key(&opts.key, &opts.value)?,
+
+                ConfigCommand::Unset(ref opts) => cfg::unset_key(&opts.key)?,
+
                 ConfigCommand::Import(ref opts) => {
                     cfg::import(opts)?;
                 }
 
-                ConfigCommand::Show => {
-                    eprintln!("{:#?}", *CFG);
-                }
+                ConfigCommand::Profile(ref profile) => match profile.command {
+                    ProfileCommand::List => {
+                        for name in cfg::list_profiles()? {
+                            if name == cfg::profile() {
+                                println!("{} {}", style("*").bold().green(), style(name).bold());
+                            } else {
+                                println!("  {name}");
+                            }
+                        }
+                    }
+                    ProfileCommand::Create { ref name } => {
+                        let folder = cfg::create_profile(name)?;
+                        println!("created profile {name} in {folder}");
+                        println!(
+                            "next: neorg-task-sync --profile {name} config import client-secret"
+                        );
+                    }
+                    ProfileCommand::Delete { ref name, yes } => {
+                        if yes || confirm(&format!("Delete profile {name} with all its tokens?"))? {
+                            cfg::delete_profile(name)?;
+                            println!("deleted profile {name}");
+                        }
+                    }
+                },
+
+                ConfigCommand::Show(ref opts) => cfg::show(opts)?,
 
                 ConfigCommand::TaskList(ref tl) => match &tl.operation {
                     ConfigOperation::List => {
@@ -74,14 +150,16 @@ pub async fn run(opts: &Opts) -> Result<(), Error> {
                                 choice.id
                             }
                         };
-                        let mut cfg = CFG.clone();
-                        cfg.tasklist = value;
-                        cfg.store_fallback()?;
+                        cfg::set_key_value("tasklist", value.as_ref().into())?;
                     }
                 },
             };
         }
 
+        Command::Dashboard(ref opts) => perform_dashboard(opts).await?,
+
+        Command::Doctor => perform_doctor(local_config).await?,
+
         Command::Generate(ref gen) => match gen.target {
             GenerateTarget::HelpMarkdown => println!("{}", clap_markdown::help_markdown::<Opts>()),
             GenerateTarget::Completion(ref comp_opts) => {
@@ -91,6 +169,14 @@ pub async fn run(opts: &Opts) -> Result<(), Error> {
             }
         },
 
+        Command::Init => perform_init().await?,
+
+        Command::Lint(ref opts) => perform_lint(opts).await?,
+
+        Command::Nvim => nvim::serve().await.during("serving neovim rpc")?,
+
+        Command::Open(ref opts) => perform_open(opts).await?,
+
         Command::Parse(ref parse) => match parse.target.extension() {
             Some(norg) if norg == "norg" || parse.force_norg => {
                 let mut norg = ParsedNorg::open(&parse.target)?;
@@ -114,18 +200,18 @@ pub async fn run(opts: &Opts) -> Result<(), Error> {
                 })
             }
         },
-        Command::Sync(ref sync) => perform_sync(auth::login().await?, sync).await?,
-
-        Command::Tasks(ref opts) => {
-            let tasks = get_tasks(auth::login().await?, &CFG.tasklist).await?;
-            if opts.json {
-                io::stdout().write_all(serde_json::to_string(&tasks)?.as_bytes())?;
-            } else {
-                for task in tasks {
-                    log::info!("{task:#?}");
-                }
-            }
-        }
+        Command::Rollover(ref opts) => perform_rollover(opts)?,
+
+        Command::Search(ref opts) => perform_search(opts)?,
+
+        Command::Sync(ref sync) => perform_sync(sync).await?,
+
+        Command::Tasklist(ref opts) => perform_tasklist(&opts.command).await?,
+
+        Command::Tasks(ref opts) => match opts.command {
+            Some(ref command) => perform_tasks_command(command).await?,
+            None => perform_tasks_list(opts).await?,
+        },
     }
     Ok(())
 }
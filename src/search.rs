@@ -0,0 +1,253 @@
+use chrono::NaiveDate;
+use console::style;
+use regex::RegexBuilder;
+use serde::{Serialize, Serializer};
+use skim::SkimItem;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::editor::open_in_editor;
+use crate::error::Error;
+use crate::opts::{Search as SearchOpts, SearchFormat, StateFilter};
+use crate::parse::{ParsedNorg, State};
+use crate::select::select_plain_single;
+use crate::sync::workspace_files;
+
+/// Todo found in the workspace.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hit {
+    pub file: PathBuf,
+    /// 0-based, as everywhere else.
+    pub line: usize,
+    #[serde(serialize_with = "serialize_state")]
+    pub state: State,
+    pub content: Arc<str>,
+    pub id: Option<Arc<str>>,
+    pub section: Option<Arc<str>>,
+    /// Due date or, for journal files, the day of the file.
+    pub date: Option<NaiveDate>,
+    #[serde(skip)]
+    pub file_day: Option<NaiveDate>,
+}
+
+impl Hit {
+    pub fn location(&self) -> String {
+        format!("{}:{}", self.file.display(), self.line + 1)
+    }
+}
+
+impl SkimItem for Hit {
+    fn text(&self) -> Cow<str> {
+        Cow::Owned(format!("{} {}", self.content, self.location()))
+    }
+}
+
+fn serialize_state<S: Serializer>(state: &State, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(state.name())
+}
+
+pub fn perform_search(opts: &SearchOpts) -> Result<(), Error> {
+    let hits = search(opts)?;
+
+    let format = if opts.edit {
+        SearchFormat::Skim
+    } else {
+        opts.format
+    };
+    match format {
+        SearchFormat::Text => {
+            for hit in &hits {
+                print_hit(hit);
+            }
+        }
+        SearchFormat::Json => println!("{}", serde_json::to_string(&hits)?),
+        SearchFormat::Skim => {
+            let Some(hit) = select_plain_single(hits) else {
+                return Ok(());
+            };
+            if opts.edit {
+                open_in_editor(&hit.file, hit.line)?;
+            } else {
+                println!("{}", hit.location());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// All todos in the given files or folders (default: config value `workspaces`).
+pub fn local_todos<P: AsRef<Path>>(files_or_folders: &[P]) -> Result<Vec<Hit>, Error> {
+    let mut hits = Vec::new();
+    for file in workspace_files(files_or_folders)? {
+        let norg = ParsedNorg::open(&file)?;
+        let file_day = norg.parse_filename_day().ok();
+        hits.extend(norg.todos.iter().map(|todo| Hit {
+            file: file.clone(),
+            line: todo.line,
+            state: todo.state,
+            content: todo.content.clone(),
+            id: todo.id.clone(),
+            section: norg.section_of(todo.line).map(|s| s.title.clone()),
+            date: todo.due_at.or(file_day),
+            file_day,
+        }));
+    }
+    Ok(hits)
+}
+
+fn search(opts: &SearchOpts) -> Result<Vec<Hit>, Error> {
+    filter(local_todos(&opts.files_or_folders[..])?, opts)
+}
+
+fn filter(mut hits: Vec<Hit>, opts: &SearchOpts) -> Result<Vec<Hit>, Error> {
+    let re = RegexBuilder::new(&opts.pattern)
+        .case_insensitive(true)
+        .build()?;
+
+    hits.retain(|hit| {
+        let state_matches = match opts.state {
+            None => true,
+            Some(StateFilter::Open) => hit.state != State::Done,
+            Some(StateFilter::Undone) => hit.state == State::Undone,
+            Some(StateFilter::Pending) => hit.state == State::Pending,
+            Some(StateFilter::Done) => hit.state == State::Done,
+        };
+        let date_matches = match (opts.from, opts.to) {
+            (None, None) => true,
+            (from, to) => hit.date.is_some_and(|d| {
+                !from.is_some_and(|from| d < from) && !to.is_some_and(|to| d > to)
+            }),
+        };
+        let file_day = hit.file_day.map(|d| d.format("%Y-%m-%d").to_string());
+        let text_matches = [
+            Some(&*hit.content),
+            Some(hit.state.name()),
+            hit.id.as_deref(),
+            hit.section.as_deref(),
+            file_day.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|field| re.is_match(field));
+
+        state_matches && date_matches && text_matches
+    });
+    Ok(hits)
+}
+
+fn print_hit(hit: &Hit) {
+    let details: Vec<String> = [
+        hit.section.as_ref().map(|s| s.to_string()),
+        hit.date.map(|d| d.format("%Y-%m-%d").to_string()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let details = if details.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", details.join(", "))
+    };
+    println!(
+        "{location} [{state}] {content}{details}",
+        location = style(hit.location()).bold(),
+        state = hit.state.name(),
+        content = hit.content,
+        details = style(details).dim(),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn day(d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(2023, 10, d)
+    }
+
+    fn hits() -> Vec<Hit> {
+        [
+            (State::Undone, "Buy milk", None, day(17)),
+            (State::Pending, "Call bob", Some("Errands"), day(18)),
+            (State::Done, "Buy bread", None, day(19)),
+            (State::Undone, "Read docs", None, None),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(line, (state, content, section, date))| Hit {
+            file: PathBuf::from("2023-10-18.norg"),
+            line,
+            state,
+            content: content.into(),
+            id: None,
+            section: section.map(Arc::from),
+            date,
+            file_day: day(18),
+        })
+        .collect()
+    }
+
+    fn lines(
+        pattern: &str,
+        state: Option<StateFilter>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Vec<usize> {
+        let opts = SearchOpts {
+            pattern: pattern.into(),
+            files_or_folders: Vec::new(),
+            state,
+            from,
+            to,
+            format: SearchFormat::Text,
+            edit: false,
+        };
+        filter(hits(), &opts)
+            .unwrap()
+            .iter()
+            .map(|h| h.line)
+            .collect()
+    }
+
+    #[test]
+    fn filters() {
+        // regex on content, state, section and file day
+        assert_eq!(lines("^buy", None, None, None), vec![0, 2]);
+        assert_eq!(lines("pending", None, None, None), vec![1]);
+        assert_eq!(lines("errands", None, None, None), vec![1]);
+        assert_eq!(lines("2023-10-18", None, None, None), vec![0, 1, 2, 3]);
+
+        assert_eq!(
+            lines("", Some(StateFilter::Open), None, None),
+            vec![0, 1, 3]
+        );
+        assert_eq!(lines("", Some(StateFilter::Undone), None, None), vec![0, 3]);
+        assert_eq!(lines("", Some(StateFilter::Done), None, None), vec![2]);
+
+        // undated todos are dropped by any date filter
+        assert_eq!(lines("", None, day(18), None), vec![1, 2]);
+        assert_eq!(lines("", None, None, day(18)), vec![0, 1]);
+        assert_eq!(lines("", None, day(18), day(18)), vec![1]);
+        assert_eq!(
+            lines("buy", Some(StateFilter::Open), day(17), None),
+            vec![0]
+        );
+
+        assert!(filter(
+            Vec::new(),
+            &SearchOpts {
+                pattern: "(".into(),
+                files_or_folders: Vec::new(),
+                state: None,
+                from: None,
+                to: None,
+                format: SearchFormat::Text,
+                edit: false,
+            }
+        )
+        .is_err());
+    }
+}
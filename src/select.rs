@@ -1,5 +1,6 @@
 use regex::Regex;
 use skim::prelude::*;
+use std::io::{self, BufRead, Write};
 
 #[allow(dead_code)]
 pub fn select_with_preview<E: SkimItem + Clone>(entries: &[E]) -> Vec<E> {
@@ -16,7 +17,6 @@ pub fn select_with_preview<E: SkimItem + Clone>(entries: &[E]) -> Vec<E> {
     }
 }
 
-#[allow(dead_code)]
 pub fn select_with_regex<E: SkimItem + Clone>(
     entries: &[E],
     regex: &str,
@@ -35,7 +35,6 @@ pub fn select_plain<E: SkimItem + Clone>(entries: &[E]) -> Vec<E> {
     select_via_builder(entries, SkimOptionsBuilder::default().multi(true))
 }
 
-#[allow(dead_code)]
 pub fn select_plain_single<E: SkimItem + Clone>(mut entries: Vec<E>) -> Option<E> {
     match entries.len() {
         0..=1 => entries.pop(),
@@ -43,6 +42,30 @@ pub fn select_plain_single<E: SkimItem + Clone>(mut entries: Vec<E>) -> Option<E
     }
 }
 
+/// Ask a yes/no question on the terminal, defaulting to no.
+pub fn confirm(question: &str) -> io::Result<bool> {
+    eprint!("{question} [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Ask for a line of input on the terminal, empty input selects the default.
+pub fn prompt(question: &str, default: Option<&str>) -> io::Result<String> {
+    match default {
+        Some(default) => eprint!("{question} [{default}]: "),
+        None => eprint!("{question}: "),
+    }
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(match answer.trim() {
+        "" => default.unwrap_or_default().to_owned(),
+        answer => answer.to_owned(),
+    })
+}
+
 #[allow(dead_code)]
 fn select_via_builder<E: SkimItem + Clone>(
     entries: &[E],
@@ -0,0 +1,38 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, rename, File};
+use std::io;
+use std::sync::Arc;
+
+use crate::cfg::state_name;
+use crate::error::{Error, WrapError};
+
+/// State that is kept between syncs.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    /// When todos were first seen as completed locally, keyed by task id (or file and content for
+    /// todos without id).
+    #[serde(default)]
+    pub done_first_seen: HashMap<Arc<str>, DateTime<Local>>,
+}
+
+impl SyncState {
+    pub fn load() -> Result<Self, Error> {
+        match File::open(state_name()) {
+            Ok(file) => Ok(serde_json::from_reader(file).during("parsing sync state")?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn store(&self) -> Result<(), Error> {
+        if let Some(folder) = state_name().parent() {
+            fs::create_dir_all(folder).during("creating folder for sync state")?;
+        }
+        let tmp = state_name().with_extension("json.new");
+        serde_json::to_writer(File::create(&tmp)?, self)?;
+        rename(tmp, state_name())?;
+        Ok(())
+    }
+}
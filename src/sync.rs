@@ -1,24 +1,79 @@
-use chrono::Duration;
-use console::{Style, StyledObject};
+use chrono::{Duration, Local};
+use console::{style, Style, StyledObject};
 use google_tasks1::api::Task as GTask;
 use indicatif::ProgressIterator;
 use once_cell::sync::Lazy;
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 use std::{fs, io};
 
-use crate::auth::Authenticator;
-use crate::cfg::CFG;
+use crate::archive::archive;
+use crate::auth::{self, Authenticator};
+use crate::cfg::{JournalLayout, CFG};
+use crate::dashboard::{is_generated, write_dashboard};
+use crate::journal;
+use crate::opts::ReportFormat;
 use crate::opts::Sync as SyncOpts;
 use crate::parse::{ParsedNorg, State, Todo};
 use crate::progress_bar::style_progress_bar_count;
+use crate::report::{Cleared, FileReport, SyncReport, SCHEMA_VERSION};
+use crate::rollover::rollover;
+use crate::state::SyncState;
 use crate::tasks::{clear_tasks, get_tasks, task_complete, task_create, task_update, Task};
 use crate::Error;
 
-pub async fn perform_sync(auth: Authenticator, opts: &SyncOpts) -> Result<(), Error> {
-    let tasklist = CFG.tasklist.clone();
+pub async fn perform_sync(opts: &SyncOpts) -> Result<(), Error> {
+    let timer = Instant::now();
+    let mut report = SyncReport {
+        schema_version: SCHEMA_VERSION,
+        tasklist: CFG.tasklist.clone(),
+        started_at: Local::now(),
+        duration_ms: 0,
+        files: Vec::new(),
+        cleared: Cleared::new(CFG.clear_completed_tasks_older_than_days, &[]),
+        errors: Vec::new(),
+    };
+
+    match sync_all(opts, &mut report).await {
+        Ok(()) => {}
+        // machine readable reports are always printed, including the error that stopped the sync
+        Err(err) if opts.report != ReportFormat::Text => report.errors.push(err.to_string()),
+        Err(err) => return Err(err),
+    }
+    report.duration_ms = timer.elapsed().as_millis() as u64;
+
+    if opts.report == ReportFormat::Text {
+        print_summary(&report);
+    } else {
+        report.write(opts.report)?;
+    }
+
+    if report.has_errors() {
+        return Err(Error::SyncFailed {
+            num: report.files.iter().filter(|f| f.error.is_some()).count() + report.errors.len(),
+        });
+    }
+
+    Ok(())
+}
+
+// Sync all files, recording the results in the report. Errors that stop the sync are returned.
+async fn sync_all(opts: &SyncOpts, report: &mut SyncReport) -> Result<(), Error> {
+    // machine readable reports are always printed, so we record errors and continue
+    let keep_going = opts.report != ReportFormat::Text;
+
+    let tasklist = report.tasklist.clone();
+    let today = if opts.pull_to_today {
+        Some(journal::create_today(&journal::journal_folder(
+            &opts.files_or_folders[..],
+        )?)?)
+    } else {
+        None
+    };
     let files = {
         let mut files =
             get_files_from_folders(&opts.files_or_folders[..], &CFG.ignore_filenames[..])?;
@@ -27,14 +82,45 @@ pub async fn perform_sync(auth: Authenticator, opts: &SyncOpts) -> Result<(), Er
         }
         files
     };
+    if files.is_empty() {
+        return Err(Error::NotFound {
+            what: "norg files to sync".into(),
+        });
+    }
+
+    let idx_pull = match today {
+        Some(ref today) => {
+            let today = fs::canonicalize(today)?;
+            files
+                .iter()
+                .position(|f| fs::canonicalize(f).is_ok_and(|f| f == today))
+                .ok_or_else(|| Error::NotFound {
+                    what: format!(
+                        "journal file for today among synced files: {}",
+                        today.display()
+                    ),
+                })?
+        }
+        None if opts.pull_to_first => 0,
+        None => files.len() - 1,
+    };
 
+    if opts.rollover {
+        for stats in rollover(&files[..], &files[idx_pull], CFG.rollover_leave)? {
+            if opts.report == ReportFormat::Text {
+                println!("{stats}");
+            }
+        }
+    }
+
+    let auth = auth::login().await?;
     let mut todos = Vec::new();
     let mut tasks = get_tasks(auth.clone(), &tasklist).await?;
     let original_tasks = tasks.clone();
 
-    let mut stats = Vec::new();
+    let reports = &mut report.files;
+    let errors = &mut report.errors;
 
-    let idx_last = files.len() - 1;
     for (i, file) in files
         .iter()
         .enumerate()
@@ -42,71 +128,134 @@ pub async fn perform_sync(auth: Authenticator, opts: &SyncOpts) -> Result<(), Er
         .with_message("Syncing…")
     {
         // Skip the file we want to pull to
-        match (i, opts.pull_to_first) {
-            (0, true) => continue,
-            (idx, false) if idx == idx_last => continue,
-            _ => {}
+        if i == idx_pull {
+            continue;
         }
 
         let mut syncer = Syncer::from_opts(opts, tasklist.clone());
         syncer.pull_new = false;
 
-        let result = syncer.perform(auth.clone(), file, &tasks[..]).await?;
-        tasks = result.tasks_after;
-        todos.extend(result.todos_present);
+        let timer_file = Instant::now();
+        match syncer.perform(auth.clone(), file, &tasks[..]).await {
+            Ok(result) => {
+                tasks = result.tasks_after;
+                todos.extend(result.todos_present);
 
-        stats.push(result.stats);
+                reports.push(FileReport::new(result.stats, timer_file.elapsed()));
+            }
+            Err(err) if keep_going => {
+                reports.push(FileReport::failed(file.clone(), &err, timer_file.elapsed()));
+            }
+            Err(err) => return Err(err),
+        }
     }
 
     // Sync file that we pull to
-    let present_todo_ids: Vec<Arc<str>> = todos.iter().filter_map(|t| t.id.clone()).collect();
-    // tasks that were actually created new
-    let new_remote_tasks = original_tasks
-        .iter()
-        .filter(|t| !present_todo_ids.contains(&t.id))
-        .cloned()
-        .collect::<Vec<_>>();
+    let file_to_pull = &files[idx_pull];
+
+    if reports.iter().any(|r| r.error.is_some()) {
+        // we do not know which tasks are present in the failed files
+        errors.push(format!(
+            "not syncing {} because of previous errors",
+            file_to_pull.display()
+        ));
+    } else {
+        let present_todo_ids: Vec<Arc<str>> = todos.iter().filter_map(|t| t.id.clone()).collect();
+        // tasks that were actually created new
+        let new_remote_tasks = original_tasks
+            .iter()
+            .filter(|t| !present_todo_ids.contains(&t.id))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let timer_file = Instant::now();
+        let report = match Syncer::from_opts(opts, tasklist.clone())
+            .perform(auth.clone(), file_to_pull, &new_remote_tasks[..])
+            .await
+        {
+            Ok(result) => FileReport::new(result.stats, timer_file.elapsed()),
+            Err(err) if keep_going => {
+                FileReport::failed(file_to_pull.clone(), &err, timer_file.elapsed())
+            }
+            Err(err) => return Err(err),
+        };
+        reports.insert(idx_pull, report);
+    }
 
-    let file_to_pull = &files[if opts.pull_to_first { 0 } else { idx_last }];
+    if let Some(days) = CFG.archive_completed_todos_older_than_days {
+        // files that failed (or were skipped) might not reflect the remote state
+        let synced: Vec<PathBuf> = reports
+            .iter()
+            .filter(|r| r.error.is_none())
+            .map(|r| r.file.clone())
+            .collect();
+        let archived = SyncState::load().and_then(|mut state| {
+            let archived = archive(
+                &synced[..],
+                &tasks[..],
+                &mut state,
+                Duration::days(days as i64),
+            )?;
+            state.store()?;
+            Ok(archived)
+        });
+        match archived {
+            Ok(archived) if opts.report == ReportFormat::Text => {
+                for stats in archived {
+                    println!("{stats}");
+                }
+            }
+            Ok(_) => {}
+            Err(err) if keep_going => errors.push(format!("archiving completed todos: {err}")),
+            Err(err) => return Err(err),
+        }
+    }
 
-    let result = Syncer::from_opts(opts, tasklist.clone())
-        .perform(auth.clone(), file_to_pull, &new_remote_tasks[..])
-        .await?;
-    if opts.pull_to_first {
-        stats.insert(0, result.stats);
-    } else {
-        stats.push(result.stats);
+    if let Some(ref dashboard) = CFG.dashboard_file {
+        match write_dashboard(dashboard.as_std_path(), &files[..], &tasks[..]) {
+            Ok(()) => {}
+            Err(err) if keep_going => errors.push(format!("writing dashboard: {err}")),
+            Err(err) => return Err(err),
+        }
     }
 
-    let num_deleted = if let Some(days) = CFG.clear_completed_tasks_older_than_days {
-        let (tasks, num_deleted) =
-            clear_tasks(auth, &tasklist, tasks, Duration::days(days as i64)).await?;
-        log::info!(
-            "Number of tasks not completed/old enough yet: {}",
-            tasks.len()
-        );
-        num_deleted
+    if let Some(days) = CFG.clear_completed_tasks_older_than_days {
+        match clear_tasks(auth, &tasklist, tasks, Duration::days(days as i64)).await {
+            Ok((tasks, deleted)) => {
+                log::info!(
+                    "Number of tasks not completed/old enough yet: {}",
+                    tasks.len()
+                );
+                report.cleared = Cleared::new(Some(days), &deleted[..]);
+            }
+            Err(err) if keep_going => errors.push(format!("clearing completed tasks: {err}")),
+            Err(err) => return Err(err),
+        }
     } else {
         log::info!("Not clearing old completed tasks.");
-        0
-    };
+    }
+
+    Ok(())
+}
 
-    for s in stats.iter().filter(|s| s.any_change()) {
-        println!("{s}");
+fn print_summary(report: &SyncReport) {
+    for file in report.files.iter() {
+        let stats = SyncStats::from(file);
+        if stats.any_change() {
+            println!("{stats}");
+        }
     }
 
-    if num_deleted > 0 {
+    if report.cleared.count > 0 {
         println!(
             "Cleared {} completed tasks older than {} days…",
-            num_deleted,
-            CFG.clear_completed_tasks_older_than_days.unwrap()
+            report.cleared.count,
+            report.cleared.older_than_days.unwrap_or_default()
         );
     }
-
-    Ok(())
 }
 
-fn get_files_from_folders<P, S>(
+pub fn get_files_from_folders<P, S>(
     files_or_folders: &[P],
     ignored_filenames: &[S],
 ) -> Result<Vec<PathBuf>, Error>
@@ -130,18 +279,51 @@ where
                 if p.is_file()
                     && p.extension() == Some(&OsString::from("norg"))
                     && !ignored_filenames.contains(&file_name.as_ref())
+                    && CFG.archive_file.as_deref() != Some(file_name.as_ref())
+                    && !is_dashboard(&p)
                 {
                     files.push(p);
+                } else if p.is_dir()
+                    && CFG.journal_layout == JournalLayout::Nested
+                    && !file_name.starts_with('.')
+                {
+                    files.extend(get_files_from_folders(&[p], &ignored_filenames[..])?);
                 }
             }
-        } else if p.is_file() {
+        } else if p.is_file() && !is_dashboard(p) {
             files.push(p.to_owned());
         }
     }
     Ok(files)
 }
 
-struct Syncer {
+// Norg files in the given files/folders or, if none are given, in the configured workspaces.
+pub fn workspace_files<P: AsRef<Path>>(files_or_folders: &[P]) -> Result<Vec<PathBuf>, Error> {
+    let mut files = if !files_or_folders.is_empty() {
+        get_files_from_folders(files_or_folders, &CFG.ignore_filenames[..])?
+    } else if !CFG.workspaces.is_empty() {
+        get_files_from_folders(&CFG.workspaces[..], &CFG.ignore_filenames[..])?
+    } else {
+        return Err(Error::NotFound {
+            what: "files or folders (specify them or configure `workspaces`)".into(),
+        });
+    };
+    files.sort();
+    Ok(files)
+}
+
+// Generated dashboards must never be synced themselves, including those written via `--output`.
+fn is_dashboard(path: &Path) -> bool {
+    let configured = CFG.dashboard_file.as_ref().is_some_and(|dashboard| {
+        match (fs::canonicalize(dashboard), fs::canonicalize(path)) {
+            (Ok(dashboard), Ok(path)) => dashboard == path,
+            _ => false,
+        }
+    });
+    configured || is_generated(path)
+}
+
+pub struct Syncer {
     fix_missing: bool,
 
     pull_completed: bool,
@@ -153,14 +335,45 @@ struct Syncer {
 }
 
 #[derive(Debug, Clone)]
-struct SyncStats {
-    file: PathBuf,
-    num_pull_completed: usize,
-    num_push_completed: usize,
-    num_pull_new: usize,
-    num_push_new: usize,
-    num_newer_local: usize,
-    num_newer_remote: usize,
+pub struct SyncStats {
+    pub file: PathBuf,
+    pub pull_completed: Vec<TaskRef>,
+    pub push_completed: Vec<TaskRef>,
+    pub pull_new: Vec<TaskRef>,
+    pub push_new: Vec<TaskRef>,
+    pub newer_local: Vec<TaskRef>,
+    pub newer_remote: Vec<TaskRef>,
+    pub malformed_id: Vec<TaskRef>,
+}
+
+/// Task/todo affected by a sync operation.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskRef {
+    pub id: Option<Arc<str>>,
+    pub title: Arc<str>,
+    /// Line of the todo (0-based), if it is a local todo.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+impl From<&Todo> for TaskRef {
+    fn from(todo: &Todo) -> Self {
+        Self {
+            id: todo.id.clone(),
+            title: todo.content.clone(),
+            line: Some(todo.line),
+        }
+    }
+}
+
+impl From<&Task> for TaskRef {
+    fn from(task: &Task) -> Self {
+        Self {
+            id: Some(task.id.clone()),
+            title: task.title.clone(),
+            line: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -207,7 +420,9 @@ impl Diff {
                 continue;
             }
 
-            let due_date_differs = task.due_at != todo.due_at;
+            // Locally, only todos in `section_todos_till_end_of_day` have a due date. Other todos
+            // do not know the remote one (e.g. from `capture --due`), which must not be cleared.
+            let due_date_differs = todo.due_at.is_some() && task.due_at != todo.due_at;
 
             if due_date_differs && local_newer {
                 newer_local.insert(id, todo.clone());
@@ -243,29 +458,34 @@ impl std::fmt::Display for SyncStats {
 
         write!(f, "{file}: {completed} {pulled} {pull_completed} {pushed} {push_completed} | {new} {pulled} {pull_new} {pushed} {push_new} | {updated} {pulled} {newer_remote} {pushed} {newer_local}",
         file=self.file.display(),
-        pull_completed=STYLE_DONE.apply_to(self.num_pull_completed),
-        push_completed=STYLE_DONE.apply_to(self.num_push_completed),
-        pull_new=STYLE_NEW.apply_to(self.num_pull_new),
-        push_new=STYLE_NEW.apply_to(self.num_push_new),
-        newer_local=STYLE_UPDATE.apply_to(self.num_newer_local),
-        newer_remote=STYLE_UPDATE.apply_to(self.num_newer_remote),
-        )
+        pull_completed=STYLE_DONE.apply_to(self.pull_completed.len()),
+        push_completed=STYLE_DONE.apply_to(self.push_completed.len()),
+        pull_new=STYLE_NEW.apply_to(self.pull_new.len()),
+        push_new=STYLE_NEW.apply_to(self.push_new.len()),
+        newer_local=STYLE_UPDATE.apply_to(self.newer_local.len()),
+        newer_remote=STYLE_UPDATE.apply_to(self.newer_remote.len()),
+        )?;
+        if !self.malformed_id.is_empty() {
+            write!(
+                f,
+                " | {} {}",
+                style("malformed id").bold().red(),
+                style(self.malformed_id.len()).red()
+            )?;
+        }
+        Ok(())
     }
 }
 
 impl SyncStats {
-    fn any_change(&self) -> bool {
-        (self.num_pull_new
-            + self.num_pull_completed
-            + self.num_push_new
-            + self.num_push_completed
-            + self.num_newer_local
-            + self.num_newer_remote)
-            > 0
+    pub fn any_change(&self) -> bool {
+        self.modified_file() || !(self.push_completed.is_empty() && self.newer_local.is_empty())
     }
-    fn modified_file(&self) -> bool {
-        (self.num_pull_new + self.num_pull_completed + self.num_push_new + self.num_newer_remote)
-            > 0
+    pub fn modified_file(&self) -> bool {
+        !(self.pull_new.is_empty()
+            && self.pull_completed.is_empty()
+            && self.push_new.is_empty()
+            && self.newer_remote.is_empty())
     }
 }
 
@@ -286,29 +506,50 @@ impl Syncer {
     ) -> Result<SyncResult, Error> {
         let mut norg = ParsedNorg::open(file)?;
 
+        let (tasks_after, stats) = self.perform_norg(auth, &mut norg, tasks).await?;
+
+        if stats.modified_file() {
+            norg.backup()?;
+            norg.write()?;
+        }
+        Ok(SyncResult {
+            tasks_after,
+            todos_present: norg.todos,
+            stats,
+        })
+    }
+
+    // Perform full sync on already parsed norg data without writing anything to disk.
+    pub async fn perform_norg(
+        &self,
+        auth: Authenticator,
+        norg: &mut ParsedNorg,
+        tasks: &[Task],
+    ) -> Result<(Vec<Task>, SyncStats), Error> {
+        let file = norg.filename.clone();
+
         let mut tasks_after: Vec<Task> = tasks.to_vec();
 
-        let mut num_pull_completed = 0;
-        let mut num_push_completed = 0;
-        let mut num_pull_new = 0;
-        let mut num_push_new = 0;
+        let mut pull_completed = Vec::new();
+        let mut push_completed = Vec::new();
+        let mut pull_new = Vec::new();
+        let mut push_new = Vec::new();
 
         log::trace!("Pre-pull completed:\n{norg:#?}");
         if self.pull_completed {
-            num_pull_completed = sync_pull_completed(tasks, &mut norg)?;
+            pull_completed = sync_pull_completed(tasks, norg)?;
         }
         log::trace!("Pre-pull new:\n{norg:#?}");
         if self.pull_new {
-            num_pull_new = sync_pull_new(tasks, &mut norg)?;
+            pull_new = sync_pull_new(tasks, norg)?;
         }
 
         log::trace!("Pre-push completed:\n{norg:#?}");
         if self.push_completed {
-            num_push_completed =
-                sync_push_completed(auth.clone(), &self.tasklist, &mut norg, tasks).await?;
+            push_completed = sync_push_completed(auth.clone(), &self.tasklist, norg, tasks).await?;
         }
 
-        let missing = check_missing_remote_tasks(&tasks_after[..], &norg);
+        let missing = check_missing_remote_tasks(&tasks_after[..], norg);
         if self.fix_missing {
             let missing_idx = norg
                 .todos
@@ -326,26 +567,35 @@ impl Syncer {
             log::info!("Clearing {num} tasks that are not present remote to re-create them.");
             norg.clear_tags(&missing_idx)?;
         } else {
-            warn_missing_remote_tasks(file, missing);
+            warn_missing_remote_tasks(&file, missing);
         }
 
         log::trace!("Pre-push new:\n{norg:#?}");
         if self.push_new {
-            let pushed = sync_push_new(auth.clone(), &self.tasklist, &mut norg).await?;
-            num_push_new = pushed.len();
+            let pushed = sync_push_new(auth.clone(), &self.tasklist, norg).await?;
+            push_new = pushed.iter().map(TaskRef::from).collect();
             tasks_after.extend(pushed);
         }
 
-        let diff = Diff::compute(&norg, &tasks_after[..])?;
+        let malformed_id: Vec<TaskRef> = norg
+            .todos
+            .iter()
+            .filter(|t| t.malformed_id && t.state == State::Undone)
+            .map(TaskRef::from)
+            .collect();
+        warn_malformed_ids(&file, &malformed_id[..]);
+
+        let diff = Diff::compute(norg, &tasks_after[..])?;
 
         let stats = SyncStats {
-            file: file.to_path_buf(),
-            num_pull_completed,
-            num_push_completed,
-            num_pull_new,
-            num_push_new,
-            num_newer_local: diff.newer_local.len(),
-            num_newer_remote: diff.newer_remote.len(),
+            file,
+            pull_completed,
+            push_completed,
+            pull_new,
+            push_new,
+            newer_local: diff.newer_local.values().map(TaskRef::from).collect(),
+            newer_remote: diff.newer_remote.values().map(TaskRef::from).collect(),
+            malformed_id,
         };
 
         for (id, todo) in diff.newer_local {
@@ -363,15 +613,7 @@ impl Syncer {
 
         norg.update_task_titles(updates)?;
 
-        if stats.modified_file() {
-            norg.backup()?;
-            norg.write()?;
-        }
-        Ok(SyncResult {
-            tasks_after,
-            todos_present: norg.todos,
-            stats,
-        })
+        Ok((tasks_after, stats))
     }
 
     fn from_opts(opts: &SyncOpts, tasklist: Arc<str>) -> Syncer {
@@ -386,6 +628,21 @@ impl Syncer {
             tasklist,
         }
     }
+
+    // Syncer for a single editor buffer: new remote tasks are only pulled by a full sync because
+    // a single buffer cannot know which tasks are already present in other files.
+    pub fn for_buffer(tasklist: Arc<str>) -> Syncer {
+        Syncer {
+            fix_missing: false,
+
+            pull_completed: true,
+            push_completed: true,
+            pull_new: false,
+            push_new: true,
+
+            tasklist,
+        }
+    }
 }
 
 pub fn idx_by_task_id(tasks: &[Task], id: &str) -> usize {
@@ -397,7 +654,7 @@ pub fn idx_by_task_id(tasks: &[Task], id: &str) -> usize {
 }
 
 // Sync completed tasks from remote to neorg
-fn sync_pull_completed(tasks: &[Task], norg: &mut ParsedNorg) -> Result<usize, Error> {
+fn sync_pull_completed(tasks: &[Task], norg: &mut ParsedNorg) -> Result<Vec<TaskRef>, Error> {
     let remote_done: HashSet<Arc<str>> = tasks
         .iter()
         .filter_map(|t| {
@@ -426,21 +683,21 @@ fn sync_pull_completed(tasks: &[Task], norg: &mut ParsedNorg) -> Result<usize, E
         })
         .collect();
 
-    let mut count = 0;
+    let mut completed = Vec::new();
     for idx in idx_to_complete {
         norg.mark_completed(idx);
-        count += 1;
+        completed.push(TaskRef::from(&norg.todos[idx]));
     }
-    Ok(count)
+    Ok(completed)
 }
 
-// Sync completed tasks from neorg to remote, return how many were synced.
+// Sync completed tasks from neorg to remote, return which were synced.
 async fn sync_push_completed(
     auth: Authenticator,
     tasklist: &str,
     norg: &mut ParsedNorg,
     tasks: &[Task],
-) -> Result<usize, Error> {
+) -> Result<Vec<TaskRef>, Error> {
     let norg_done: HashSet<Arc<str>> = norg
         .todos
         .iter()
@@ -450,66 +707,38 @@ async fn sync_push_completed(
         })
         .collect();
 
-    let mut count = 0;
+    let mut completed = Vec::new();
     for task in tasks
         .iter()
         .filter(|t| !t.completed && norg_done.contains(&t.id))
     {
         log::info!("Marking '{title}' as done.", title = task.title);
         task_complete(auth.clone(), tasklist, &task.id).await?;
-        count += 1;
+        completed.push(TaskRef::from(task));
     }
 
-    Ok(count)
+    Ok(completed)
 }
 
 // Insert unkown remote tasks into source_code, BUT NOT the list of todos. Returns list of pulled
 // tasks.
 // Write to disk and reparse to get new tasks.
 // Does not write to disk.
-fn sync_pull_new(tasks: &[Task], norg: &mut ParsedNorg) -> Result<usize, Error> {
+fn sync_pull_new(tasks: &[Task], norg: &mut ParsedNorg) -> Result<Vec<TaskRef>, Error> {
     let norg_ids: HashSet<Arc<str>> = norg.todos.iter().filter_map(|t| t.id.clone()).collect();
 
-    let mut lines = norg.lines();
-
-    let tasks_to_create = tasks
+    let tasks_to_create: Vec<&Task> = tasks
         .iter()
-        .filter(|t| !t.completed && !norg_ids.contains(&t.id));
-
-    let mut count = 0;
-    for (i, task) in tasks_to_create.enumerate() {
-        let line_to_insert = match (
-            norg.todos.is_empty(),
-            norg.line_number.todo_section,
-            norg.line_number.section_after_todo,
-        ) {
-            (false, usize::MAX, usize::MAX) => norg.todos.last().unwrap().line + 1,
-            (false, section_todo, section_next) => {
-                norg.todos
-                    .iter()
-                    .filter(|t| section_todo < t.line && t.line < section_next)
-                    .next_back()
-                    .map(|t| t.line)
-                    .unwrap_or(section_todo)
-                    + 1
-                    + i
-            }
-            (true, usize::MAX, usize::MAX) => lines.len(),
-            (true, section_todo, _) => section_todo + 1 + i,
-        };
+        .filter(|t| !t.completed && !norg_ids.contains(&t.id))
+        .collect();
 
+    norg.insert_todo_lines(tasks_to_create.iter().map(|task| {
         let title = task.title.clone();
         let id = task.id.clone();
+        format!("- ( ) {title} %#taskid {id}%").into_bytes()
+    }))?;
 
-        lines.insert(
-            line_to_insert,
-            format!("- ( ) {title} %#taskid {id}%").into_bytes(),
-        );
-        count += 1;
-    }
-    norg.set_lines(&lines[..])?;
-
-    Ok(count)
+    Ok(tasks_to_create.into_iter().map(TaskRef::from).collect())
 }
 
 // Create unknown task and update the source code to contain the task ids.
@@ -522,11 +751,7 @@ async fn sync_push_new(
 ) -> Result<Vec<Task>, Error> {
     let mut lines = norg.lines();
 
-    let todo_to_create: Vec<&mut Todo> = norg
-        .todos
-        .iter_mut()
-        .filter(|t| t.state == State::Undone && t.id.is_none())
-        .collect();
+    let todo_to_create: Vec<&mut Todo> = norg.todos.iter_mut().filter(|t| is_new(t)).collect();
     if todo_to_create.is_empty() {
         return Ok(Vec::new());
     }
@@ -540,6 +765,12 @@ async fn sync_push_new(
     Ok(new_tasks)
 }
 
+// Undone todo without remote task yet. Pushing todos with malformed id would create duplicates of
+// their remote task.
+fn is_new(todo: &Todo) -> bool {
+    todo.state == State::Undone && todo.id.is_none() && !todo.malformed_id
+}
+
 // Check for undone todos with ID that do not have a corresponding task remote.
 fn check_missing_remote_tasks<'a>(tasks: &[Task], norg: &'a ParsedNorg) -> Vec<&'a Todo> {
     let task_ids = tasks.iter().map(|t| t.id.clone()).collect::<HashSet<_>>();
@@ -551,6 +782,18 @@ fn check_missing_remote_tasks<'a>(tasks: &[Task], norg: &'a ParsedNorg) -> Vec<&
         .collect::<Vec<_>>()
 }
 
+fn warn_malformed_ids(filename: &Path, malformed: &[TaskRef]) {
+    for m in malformed {
+        log::warn!(
+            "{file}:{line}: todo '{task}' has a malformed task id comment and is not pushed. Repair it via `neorg-task-sync lint --fix`.",
+            file = filename.display(),
+            // norg line numbers are 1-based
+            line = m.line.unwrap_or_default() + 1,
+            task = m.title,
+        );
+    }
+}
+
 fn warn_missing_remote_tasks<'a, I: IntoIterator<Item = &'a Todo>>(filename: &Path, missing: I) {
     for m in missing.into_iter() {
         let file = filename.display();
@@ -558,3 +801,70 @@ fn warn_missing_remote_tasks<'a, I: IntoIterator<Item = &'a Todo>>(filename: &Pa
         log::warn!("{file}: task '{task}' unexpectedly deleted from Google Tasks. Sync with --fix-missing to re-create.");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+
+    static NEW_TODOS: &str = r###"* TODOs
+  - ( ) Synced %#taskid foobar1%
+  - ( ) Mangled %# taskid foobar2%
+  - ( ) Check 50% task id coverage
+  - ( ) Plain new todo
+  - (x) Done without id
+"###;
+
+    #[test]
+    fn push_skips_malformed_ids_only() -> Result<(), Error> {
+        let norg = ParsedNorg::from_source(
+            Path::new("new.norg"),
+            NEW_TODOS.as_bytes().to_vec(),
+            Local::now(),
+        )?;
+        let new: Vec<&str> = norg
+            .todos
+            .iter()
+            .filter(|t| is_new(t))
+            .map(|t| &*t.content)
+            .collect();
+        assert_eq!(new, vec!["Check 50% task id coverage", "Plain new todo"]);
+        Ok(())
+    }
+
+    // as written by `capture --push --due friday`: the file is written after the task was created
+    static CAPTURED: &str = r###"* TODOs
+  - ( ) Call bob %#taskid task1%
+"###;
+
+    #[tokio::test]
+    async fn sync_keeps_remote_due_date() -> Result<(), Error> {
+        let mut norg = ParsedNorg::from_source(
+            Path::new("2023-10-18.norg"),
+            CAPTURED.as_bytes().to_vec(),
+            Local::now(),
+        )?;
+        let task = Task {
+            completed: false,
+            id: "task1".into(),
+            title: "Call bob".into(),
+            modified_at: Local::now() - Duration::minutes(1),
+            due_at: NaiveDate::from_ymd_opt(2023, 10, 20),
+            completed_at: None,
+        };
+
+        let diff = Diff::compute(&norg, &[task.clone()])?;
+        assert!(diff.newer_local.is_empty());
+        assert!(diff.newer_remote.is_empty());
+
+        // updating the task would need google
+        let (tasks, stats) = Syncer::for_buffer("tasklist".into())
+            .perform_norg(auth::offline_authenticator().await, &mut norg, &[task])
+            .await?;
+        assert!(stats.newer_local.is_empty());
+        assert_eq!(tasks[0].due_at, NaiveDate::from_ymd_opt(2023, 10, 20));
+        Ok(())
+    }
+}
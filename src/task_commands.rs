@@ -0,0 +1,320 @@
+use chrono::Local;
+use console::{measure_text_width, pad_str, style, Alignment};
+use google_tasks1::api::Task as GTask;
+
+use crate::auth;
+use crate::auth::Authenticator;
+use crate::cfg::CFG;
+use crate::error::Error;
+use crate::opts::{Tasks as TasksOpts, TasksCommand, TasksFormat};
+use crate::parse::due_rfc3339;
+use crate::select::{confirm, select_plain_single, select_with_regex};
+use crate::tasklist::find_tasklist;
+use crate::tasks::{
+    get_single_task, get_tasks, task_complete, task_delete, task_insert, task_replace, Task,
+};
+
+pub async fn perform_tasks_command(command: &TasksCommand) -> Result<(), Error> {
+    let auth = auth::login().await?;
+    match command {
+        TasksCommand::Add { title, due, notes } => {
+            let task = GTask {
+                title: Some(title.clone()),
+                due: due.map(due_rfc3339),
+                notes: notes.clone(),
+                ..GTask::default()
+            };
+            let task = task_insert(auth, &CFG.tasklist, task).await?;
+            println!("created task {} ({})", style(&task.title).bold(), task.id);
+        }
+
+        TasksCommand::Delete { task, yes } => {
+            let task = resolve(auth.clone(), task, false).await?;
+            if *yes || confirm(&format!("Delete task {}?", task.title))? {
+                task_delete(auth, &CFG.tasklist, &task.id).await?;
+                println!("deleted task {}", task.title);
+            }
+        }
+
+        TasksCommand::Done { task } => {
+            let task = resolve(auth.clone(), task, true).await?;
+            task_complete(auth, &CFG.tasklist, &task.id).await?;
+            println!("completed task {}", style(&task.title).bold());
+        }
+
+        TasksCommand::Edit {
+            task,
+            title,
+            due,
+            no_due,
+            notes,
+        } => {
+            let task = resolve(auth.clone(), task, false).await?;
+            let mut gtask = get_single_task(auth.clone(), &CFG.tasklist, &task.id).await?;
+            if let Some(title) = title {
+                gtask.title = Some(title.clone());
+            }
+            if let Some(due) = due {
+                gtask.due = Some(due_rfc3339(*due));
+            }
+            if *no_due {
+                gtask.due = None;
+            }
+            if let Some(notes) = notes {
+                gtask.notes = Some(notes.clone());
+            }
+            let task = task_replace(auth, &CFG.tasklist, gtask).await?;
+            println!("updated task {}", style(&task.title).bold());
+        }
+
+        TasksCommand::Move { task, tasklist } => {
+            let task = resolve(auth.clone(), task, false).await?;
+            let target = find_tasklist(auth.clone(), tasklist).await?;
+            let source = get_single_task(auth.clone(), &CFG.tasklist, &task.id).await?;
+            if source.parent.is_some() {
+                log::warn!("subtask {} becomes a top-level task", task.title);
+            }
+
+            // The API cannot move tasks between lists, so the task is re-created there.
+            let copy = copy_for_insert(source);
+            let moved = task_insert(auth.clone(), &target.id, copy).await?;
+            task_delete(auth, &CFG.tasklist, &task.id).await?;
+
+            println!(
+                "moved task {} to {} (new id {})",
+                style(&moved.title).bold(),
+                target.title,
+                moved.id
+            );
+            log::warn!(
+                "todos with task id {} are no longer linked to this task",
+                task.id
+            );
+        }
+    }
+    Ok(())
+}
+
+// All fields of the task except those identifying it within its tasklist. Fields that are read-only
+// (e.g. links) are kept, too, but ignored by the API.
+fn copy_for_insert(source: GTask) -> GTask {
+    GTask {
+        etag: None,
+        id: None,
+        parent: None,
+        position: None,
+        self_link: None,
+        updated: None,
+        ..source
+    }
+}
+
+/// List the tasks of the configured tasklist, filtered according to the options.
+pub async fn perform_tasks_list(opts: &TasksOpts) -> Result<(), Error> {
+    let tasks = get_tasks(auth::login().await?, &CFG.tasklist).await?;
+    let tasks = filter_tasks(tasks, opts)?;
+
+    let format = if opts.json {
+        TasksFormat::Json
+    } else {
+        opts.format
+    };
+    match format {
+        TasksFormat::Table => print_table(&tasks[..]),
+        TasksFormat::Json => println!("{}", serde_json::to_string(&tasks)?),
+        TasksFormat::Ndjson => {
+            for task in &tasks {
+                println!("{}", serde_json::to_string(task)?);
+            }
+        }
+        TasksFormat::Csv => print_csv(&tasks[..]),
+    }
+    Ok(())
+}
+
+fn filter_tasks(tasks: Vec<Task>, opts: &TasksOpts) -> Result<Vec<Task>, Error> {
+    let tasks = match opts.title {
+        Some(ref regex) => select_with_regex(&tasks[..], regex)?,
+        None => tasks,
+    };
+    Ok(tasks
+        .into_iter()
+        .filter(|t| !(opts.open && t.completed))
+        .filter(|t| !(opts.completed && !t.completed))
+        .filter(|t| match opts.due_before {
+            Some(date) => t.due_at.is_some_and(|due| due < date),
+            None => true,
+        })
+        .filter(|t| match opts.due_after {
+            Some(date) => t.due_at.is_some_and(|due| due > date),
+            None => true,
+        })
+        .filter(|t| match opts.updated_since {
+            Some(date) => t.modified_at.date_naive() >= date,
+            None => true,
+        })
+        .collect())
+}
+
+fn print_table(tasks: &[Task]) {
+    let today = Local::now().date_naive();
+    let width = tasks
+        .iter()
+        .map(|t| measure_text_width(&t.title))
+        .max()
+        .unwrap_or(0);
+    for task in tasks {
+        let status = if task.completed { "[x]" } else { "[ ]" };
+        let due = task
+            .due_at
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        let due = match task.due_at {
+            Some(d) if d < today && !task.completed => style(format!("{due:<10}")).red().bold(),
+            _ => style(format!("{due:<10}")),
+        };
+        println!(
+            "{status} {due} {title} {updated}",
+            title = pad_str(&task.title, width, Alignment::Left, None),
+            updated = style(task.modified_at.format("%Y-%m-%d %H:%M")).dim(),
+        );
+    }
+}
+
+fn print_csv(tasks: &[Task]) {
+    println!("id,completed,due,title,updated");
+    for task in tasks {
+        let due = task
+            .due_at
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        println!(
+            "{},{},{},{},{}",
+            csv_field(&task.id),
+            task.completed,
+            due,
+            csv_field(&task.title),
+            task.modified_at.to_rfc3339(),
+        );
+    }
+}
+
+// Quote fields containing separators, quotes or line breaks (RFC 4180).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Task of the configured tasklist by id or by (case-insensitive) title query, picking one
+// interactively if the query matches several tasks.
+async fn resolve(auth: Authenticator, query: &str, only_open: bool) -> Result<Task, Error> {
+    let tasks = get_tasks(auth, &CFG.tasklist).await?;
+    if let Some(task) = tasks.iter().find(|t| &*t.id == query) {
+        return Ok(task.clone());
+    }
+
+    let query_lower = query.to_lowercase();
+    let matching: Vec<Task> = tasks
+        .into_iter()
+        .filter(|t| !(only_open && t.completed))
+        .filter(|t| t.title.to_lowercase().contains(&query_lower))
+        .collect();
+    select_plain_single(matching).ok_or_else(|| Error::NotFound {
+        what: format!("task matching '{query}'"),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::NaiveDate;
+    use clap::Parser;
+    use google_tasks1::api::TaskLinks;
+    use pretty_assertions::assert_eq;
+
+    use crate::opts::{Command, Opts};
+
+    #[test]
+    fn copy_keeps_content() {
+        let source = GTask {
+            completed: Some("2023-10-18T10:00:00.000Z".into()),
+            due: Some(due_rfc3339(NaiveDate::from_ymd_opt(2023, 10, 18).unwrap())),
+            etag: Some("etag".into()),
+            id: Some("task1".into()),
+            links: Some(vec![TaskLinks {
+                link: Some("https://example.com".into()),
+                ..TaskLinks::default()
+            }]),
+            notes: Some("notes".into()),
+            parent: Some("parent1".into()),
+            position: Some("00000000000000000001".into()),
+            status: Some("completed".into()),
+            title: Some("Buy milk".into()),
+            ..GTask::default()
+        };
+
+        let copy = copy_for_insert(source.clone());
+        assert_eq!(copy.id, None);
+        assert_eq!(copy.etag, None);
+        assert_eq!(copy.parent, None);
+        assert_eq!(copy.position, None);
+        assert_eq!(copy.completed, source.completed);
+        assert_eq!(copy.due.as_deref(), Some("2023-10-18T00:00:00+00:00"));
+        assert_eq!(
+            copy.links.unwrap()[0].link.as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(copy.notes, source.notes);
+        assert_eq!(copy.status, source.status);
+        assert_eq!(copy.title, source.title);
+    }
+
+    fn task(id: &str, completed: bool, due: Option<u32>) -> Task {
+        Task {
+            completed,
+            id: id.into(),
+            title: id.into(),
+            modified_at: Local::now(),
+            due_at: due.and_then(|d| NaiveDate::from_ymd_opt(2023, 10, d)),
+            completed_at: None,
+        }
+    }
+
+    fn filtered(args: &[&str]) -> Vec<String> {
+        let opts = Opts::try_parse_from(["neorg-task-sync", "tasks"].iter().chain(args)).unwrap();
+        let Command::Tasks(opts) = opts.command else {
+            panic!("expected tasks command");
+        };
+        let tasks = vec![
+            task("open", false, None),
+            task("done", true, Some(17)),
+            task("due", false, Some(19)),
+        ];
+        filter_tasks(tasks, &opts)
+            .unwrap()
+            .iter()
+            .map(|t| t.id.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn filter_listing() {
+        assert_eq!(filtered(&[]), ["open", "done", "due"]);
+        assert_eq!(filtered(&["--open"]), ["open", "due"]);
+        assert_eq!(filtered(&["--completed"]), ["done"]);
+        assert_eq!(filtered(&["--due-before", "2023-10-18"]), ["done"]);
+        assert_eq!(filtered(&["--due-after", "2023-10-18"]), ["due"]);
+        assert_eq!(filtered(&["--open", "--title", "^d"]), ["due"]);
+    }
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
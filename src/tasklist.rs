@@ -0,0 +1,116 @@
+use console::style;
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::auth;
+use crate::auth::Authenticator;
+use crate::cfg::CFG;
+use crate::error::Error;
+use crate::opts::TasklistCommand;
+use crate::select::{confirm, select_plain_single};
+use crate::tasks::{
+    get_tasklists, get_tasks, tasklist_create, tasklist_delete, tasklist_rename, TaskList,
+};
+
+/// Tasklist together with the number of its tasks.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskListCounts {
+    pub id: Arc<str>,
+    pub title: Arc<str>,
+    pub configured: bool,
+    pub open: usize,
+    pub completed: usize,
+}
+
+pub async fn perform_tasklist(command: &TasklistCommand) -> Result<(), Error> {
+    let auth = auth::login().await?;
+    match command {
+        TasklistCommand::Create { title } => {
+            let tl = tasklist_create(auth, title).await?;
+            println!("created tasklist {} ({})", style(&tl.title).bold(), tl.id);
+        }
+
+        TasklistCommand::Rename { tasklist, title } => {
+            let tl = find_tasklist(auth.clone(), tasklist).await?;
+            let renamed = tasklist_rename(auth, &tl.id, title).await?;
+            println!(
+                "renamed tasklist {} to {}",
+                tl.title,
+                style(&renamed.title).bold()
+            );
+        }
+
+        TasklistCommand::Delete { tasklist, yes } => {
+            let tl = find_tasklist(auth.clone(), tasklist).await?;
+            let num_tasks = get_tasks(auth.clone(), &tl.id).await?.len();
+            let configured = if tl.id == CFG.tasklist {
+                " (the configured tasklist!)"
+            } else {
+                ""
+            };
+            let question = format!(
+                "Delete tasklist {}{configured} with {num_tasks} task(s)?",
+                tl.title
+            );
+            if *yes || confirm(&question)? {
+                tasklist_delete(auth, &tl.id).await?;
+                println!("deleted tasklist {}", tl.title);
+            }
+        }
+
+        TasklistCommand::List { json } => {
+            let mut counts = Vec::new();
+            for tl in get_tasklists(auth.clone()).await? {
+                let tasks = get_tasks(auth.clone(), &tl.id).await?;
+                let completed = tasks.iter().filter(|t| t.completed).count();
+                counts.push(TaskListCounts {
+                    configured: tl.id == CFG.tasklist,
+                    open: tasks.len() - completed,
+                    completed,
+                    id: tl.id,
+                    title: tl.title,
+                });
+            }
+
+            if *json {
+                println!("{}", serde_json::to_string(&counts)?);
+            } else {
+                print_counts(&counts[..]);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Tasklist by id or (exact) title, picking one interactively if several share the title.
+pub async fn find_tasklist(auth: Authenticator, id_or_title: &str) -> Result<TaskList, Error> {
+    let tasklists = get_tasklists(auth).await?;
+    if let Some(tl) = tasklists.iter().find(|tl| &*tl.id == id_or_title) {
+        return Ok(tl.clone());
+    }
+    let by_title: Vec<TaskList> = tasklists
+        .into_iter()
+        .filter(|tl| &*tl.title == id_or_title)
+        .collect();
+    select_plain_single(by_title).ok_or_else(|| Error::NotFound {
+        what: format!("tasklist {id_or_title}"),
+    })
+}
+
+fn print_counts(counts: &[TaskListCounts]) {
+    let maxlen = counts.iter().map(|tl| tl.id.len()).max().unwrap_or(0);
+    for tl in counts {
+        let marker = if tl.configured {
+            style("*").bold().green()
+        } else {
+            style(" ")
+        };
+        println!(
+            "{marker} {id:<maxlen$} {title} ({open} open, {completed} completed)",
+            id = tl.id,
+            title = style(&tl.title).bold(),
+            open = tl.open,
+            completed = tl.completed,
+        );
+    }
+}
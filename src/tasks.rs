@@ -28,6 +28,8 @@ pub struct Task {
     pub title: Arc<str>,
     pub modified_at: DateTime<Local>,
     pub due_at: Option<NaiveDate>,
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Local>>,
 }
 
 impl TryFrom<&GTask> for Task {
@@ -57,10 +59,20 @@ impl TryFrom<&GTask> for Task {
                 .due
                 .as_ref()
                 .and_then(|d| DateTime::parse_from_rfc3339(d).ok().map(|d| d.date_naive())),
+            completed_at: task
+                .completed
+                .as_ref()
+                .and_then(|c| DateTime::parse_from_rfc3339(c).ok().map(|c| c.into())),
         })
     }
 }
 
+impl SkimItem for Task {
+    fn text(&self) -> Cow<str> {
+        Cow::Borrowed(&self.title)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TaskList {
     pub id: Arc<str>,
@@ -141,13 +153,57 @@ pub async fn get_tasklists(auth: Authenticator) -> Result<Vec<TaskList>, Error>
     tasklists.iter().map(|tl| tl.try_into()).collect()
 }
 
-// Returns list of kept tasks and number of deleted tasks
+pub async fn tasklist_create(auth: Authenticator, title: &str) -> Result<TaskList, Error> {
+    let hub = create_hub(auth);
+    let req = GTaskList {
+        title: Some(title.to_owned()),
+        ..GTaskList::default()
+    };
+    let (_response, tasklist) = hub
+        .tasklists()
+        .insert(req)
+        .doit()
+        .await
+        .during("creating tasklist")?;
+    TaskList::try_from(&tasklist)
+}
+
+pub async fn tasklist_rename(
+    auth: Authenticator,
+    tasklist: &str,
+    title: &str,
+) -> Result<TaskList, Error> {
+    let hub = create_hub(auth);
+    let req = GTaskList {
+        title: Some(title.to_owned()),
+        ..GTaskList::default()
+    };
+    let (_response, tasklist) = hub
+        .tasklists()
+        .patch(req, tasklist)
+        .doit()
+        .await
+        .during("renaming tasklist")?;
+    TaskList::try_from(&tasklist)
+}
+
+pub async fn tasklist_delete(auth: Authenticator, tasklist: &str) -> Result<(), Error> {
+    let hub = create_hub(auth);
+    hub.tasklists()
+        .delete(tasklist)
+        .doit()
+        .await
+        .during("deleting tasklist")?;
+    Ok(())
+}
+
+// Returns list of kept and deleted tasks
 pub async fn clear_tasks(
     auth: Authenticator,
     tasklist: &str,
     tasks: Vec<Task>,
     cutoff: Duration,
-) -> Result<(Vec<Task>, usize), Error> {
+) -> Result<(Vec<Task>, Vec<Task>), Error> {
     let hub = create_hub(auth);
 
     if log::log_enabled!(log::Level::Debug) {
@@ -175,7 +231,7 @@ pub async fn clear_tasks(
     {
         hub.tasks().delete(tasklist, &task.id).doit().await?;
     }
-    Ok((keep, delete.len()))
+    Ok((keep, delete))
 }
 
 pub async fn get_tasks(auth: Authenticator, tasklist: &str) -> Result<Vec<Task>, Error> {
@@ -214,10 +270,11 @@ pub async fn get_tasks(auth: Authenticator, tasklist: &str) -> Result<Vec<Task>,
             }
         }
 
+        // empty tasklists come without items
         tasks.extend(
             got_tasks
                 .items
-                .ok_or_else(|| Error::NoTasks)?
+                .unwrap_or_default()
                 .iter()
                 .map(Task::try_from)
                 .collect::<Result<Vec<Task>, Error>>()?
@@ -255,6 +312,42 @@ pub async fn task_complete(auth: Authenticator, tasklist: &str, task: &str) -> R
     Ok(())
 }
 
+pub async fn task_insert(auth: Authenticator, tasklist: &str, task: GTask) -> Result<Task, Error> {
+    let hub = create_hub(auth);
+    let (_response, task) = hub
+        .tasks()
+        .insert(task, tasklist)
+        .doit()
+        .await
+        .during("creating task")?;
+    Task::try_from(&task)
+}
+
+// Replace the remote task with the given one (identified by its id).
+pub async fn task_replace(auth: Authenticator, tasklist: &str, task: GTask) -> Result<Task, Error> {
+    let id = task.id.clone().ok_or_else(|| Error::NotFound {
+        what: "task id".into(),
+    })?;
+    let hub = create_hub(auth);
+    let (_response, task) = hub
+        .tasks()
+        .update(task, tasklist, &id)
+        .doit()
+        .await
+        .during("updating task")?;
+    Task::try_from(&task)
+}
+
+pub async fn task_delete(auth: Authenticator, tasklist: &str, task: &str) -> Result<(), Error> {
+    let hub = create_hub(auth);
+    hub.tasks()
+        .delete(tasklist, task)
+        .doit()
+        .await
+        .during("deleting task")?;
+    Ok(())
+}
+
 pub async fn task_create(
     auth: Authenticator,
     tasklist: &str,